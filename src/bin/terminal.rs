@@ -0,0 +1,260 @@
+//! Terminal frontend, for playing and debugging over SSH or on machines
+//! without a display.
+//!
+//! Draws the [`Tilemap`] with its own glyphs in a ratatui layout and reads
+//! arrow keys or WASD. Built with `--features terminal`, leaving out the Bevy
+//! frontend so it builds without a display's system libraries:
+//!
+//! ```text
+//! cargo run --no-default-features --features terminal --bin terminal -- [level [number]] [--seed N]
+//! ```
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::{Frame, Terminal};
+
+use mystuff::ghost::{GhostState, Personality, TICKS_PER_SECOND};
+use mystuff::level::{Level, LevelList};
+use mystuff::rng::GameRng;
+use mystuff::simulation::{Command, MoveDirection, Rules, Simulation};
+use mystuff::tilemap::{BOX, GOAL, PELLET, PLAYER, POWER_PELLET, VOID, WALLS};
+use mystuff::xsb::LevelPack;
+
+/// Same pace as the Bevy frontend.
+const TICK: Duration = Duration::from_millis(1000 / TICKS_PER_SECOND as u64);
+
+fn main() -> io::Result<()> {
+    let (levels, seed) = load_levels();
+    let levels = LevelList::new(levels, 0).expect("a level to play");
+    let mut sim = Simulation::from_levels(levels, seed);
+
+    // Don't leave the terminal in raw mode on the alternate screen if the
+    // game panics.
+    let panic_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        panic_hook(info);
+    }));
+
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+    let result = run(&mut terminal, &mut sim);
+    restore_terminal()?;
+    result?;
+
+    println!(
+        "Score {}, seed {}",
+        sim.state.scores.current_score,
+        sim.rng.seed()
+    );
+    Ok(())
+}
+
+/// Takes the terminal out of raw mode and back to the main screen.
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)
+}
+
+/// How long a cleared level stays up before the next one starts, as in the
+/// Bevy frontend.
+const LEVEL_COMPLETE_PAUSE: Duration = Duration::from_secs(2);
+
+/// Steps the game every [`TICK`], or on every key under Sokoban rules, until
+/// the player quits.
+fn run(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    sim: &mut Simulation,
+) -> io::Result<()> {
+    let mut pending = vec![];
+    let mut last_tick = Instant::now();
+    let mut cleared_at = None;
+    loop {
+        terminal.draw(|frame| draw(frame, sim))?;
+
+        let timeout = TICK.saturating_sub(last_tick.elapsed());
+        if event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                    return Ok(());
+                }
+                pending.extend(read_key(key.code));
+            }
+        }
+
+        // The step after the pause starts the next level.
+        if !sim.state.level_complete {
+            cleared_at = None;
+        } else if cleared_at.get_or_insert_with(Instant::now).elapsed() < LEVEL_COMPLETE_PAUSE {
+            pending.clear();
+            last_tick = Instant::now();
+            continue;
+        }
+
+        let turn_based = sim.rules == Rules::Sokoban && !pending.is_empty();
+        if turn_based || last_tick.elapsed() >= TICK {
+            sim.step(&std::mem::take(&mut pending));
+            last_tick = Instant::now();
+        }
+    }
+}
+
+fn read_key(code: KeyCode) -> Option<Command> {
+    Some(match code {
+        KeyCode::Up | KeyCode::Char('w') => Command::Move(MoveDirection::Up),
+        KeyCode::Left | KeyCode::Char('a') => Command::Move(MoveDirection::Left),
+        KeyCode::Down | KeyCode::Char('s') => Command::Move(MoveDirection::Down),
+        KeyCode::Right | KeyCode::Char('d') => Command::Move(MoveDirection::Right),
+        KeyCode::Char('r') => Command::Reset,
+        KeyCode::Char('z') => Command::Undo,
+        KeyCode::Char('y') => Command::Redo,
+        _ => return None,
+    })
+}
+
+fn draw(frame: &mut Frame, sim: &Simulation) {
+    let [map_area, status_area] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).areas(frame.area());
+
+    let name = &sim.levels.current().name;
+    let title = if name.is_empty() { "Maze" } else { name };
+    frame.render_widget(
+        Paragraph::new(map_lines(sim)).block(Block::default().borders(Borders::ALL).title(title)),
+        map_area,
+    );
+
+    let state = &sim.state;
+    let status = if state.game_over {
+        "Game over - r to restart, q to quit"
+    } else if state.level_complete {
+        "Level complete!"
+    } else if state.ghosts.frightened_ticks > 0 {
+        "Frightened"
+    } else {
+        "Playing"
+    };
+    let mut text = format!(
+        "Score {}  High {}  Lives {}  Level {}  Moves {}",
+        state.scores.current_score, state.scores.high_score, state.lives, state.level, state.moves
+    );
+    if sim.rules == Rules::Sokoban {
+        text += &format!("  Pushes {}", state.pushes);
+    }
+    text += &format!("  {status}");
+    frame.render_widget(
+        Paragraph::new(text).block(Block::default().borders(Borders::ALL)),
+        status_area,
+    );
+}
+
+/// One line of styled cells per map row, with the ghosts drawn over the top.
+fn map_lines(sim: &Simulation) -> Vec<Line<'static>> {
+    sim.tilemap
+        .0
+        .iter()
+        .enumerate()
+        .map(|(y, row)| {
+            let spans: Vec<Span> = row
+                .iter()
+                .enumerate()
+                .map(|(x, cell)| {
+                    let pos = (x as i32, y as i32);
+                    let ghost = sim.state.ghosts.list.iter().find(|g| g.pos == pos);
+                    let (glyph, color) = match ghost {
+                        Some(ghost) => match ghost.state {
+                            GhostState::Active => ('m', ghost_color(ghost.personality)),
+                            GhostState::Frightened => ('m', Color::Blue),
+                            GhostState::Eyes => ('"', Color::White),
+                        },
+                        None => cell_glyph(cell),
+                    };
+                    Span::styled(glyph.to_string(), Style::default().fg(color))
+                })
+                .collect();
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// The glyph on top of a cell's stack and the colour it is drawn in.
+fn cell_glyph(cell: &[char]) -> (char, Color) {
+    match cell.last() {
+        Some(&c) if WALLS.contains(&c) => (c, Color::Blue),
+        Some(&PLAYER) => (PLAYER, Color::Yellow),
+        Some(&c) if c == PELLET || c == POWER_PELLET => (c, Color::Yellow),
+        Some(&BOX) if cell.contains(&GOAL) => (BOX, Color::Green),
+        Some(&BOX) => (BOX, Color::Rgb(140, 90, 40)),
+        Some(&GOAL) => (GOAL, Color::Green),
+        Some(&VOID) | None => (' ', Color::Reset),
+        Some(&c) => (c, Color::Reset),
+    }
+}
+
+fn ghost_color(personality: Personality) -> Color {
+    match personality {
+        Personality::Chaser => Color::Red,
+        Personality::Ambusher => Color::LightMagenta,
+        Personality::Flanker => Color::Cyan,
+        Personality::Shy => Color::LightRed,
+    }
+}
+
+/// The levels and seed from the command line: `[level [number]] [--seed N]`,
+/// with the bundled level and a random seed by default. A level pack is
+/// played from level `number` to its end.
+fn load_levels() -> (Vec<Level>, u64) {
+    let fail = |message: String| -> ! {
+        eprintln!("{message}");
+        std::process::exit(1);
+    };
+    let mut positional = vec![];
+    let mut seed = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--seed" {
+            match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => seed = Some(n),
+                None => fail("--seed needs a number".to_string()),
+            }
+        } else {
+            positional.push(arg);
+        }
+    }
+    let seed = seed.unwrap_or_else(|| GameRng::from_entropy().seed());
+
+    let Some(path) = positional.first() else {
+        return (vec![Level::classic()], seed);
+    };
+    let source = std::fs::read_to_string(path).unwrap_or_else(|err| fail(format!("{path}: {err}")));
+    if !(path.ends_with(".sok") || path.ends_with(".xsb")) {
+        let level = Level::parse(&source).unwrap_or_else(|err| fail(format!("{path}:{err}")));
+        return (vec![level], seed);
+    }
+    let mut pack = LevelPack::parse(&source).unwrap_or_else(|err| fail(format!("{path}:{err}")));
+    let number = positional
+        .get(1)
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(1);
+    if number == 0 || number > pack.levels.len() {
+        fail(format!(
+            "{path}: has {} levels, not {number}",
+            pack.levels.len()
+        ));
+    }
+    (pack.levels.split_off(number - 1), seed)
+}
@@ -0,0 +1,271 @@
+//! Which inputs send which [`Command`]s.
+//!
+//! Inputs are named, so each frontend can match the names up with its own
+//! key codes. The file is plain text, one command per line followed by
+//! every input bound to it:
+//!
+//! ```text
+//! # Pad buttons start with `Pad`, sticks are `LeftStickUp` and so on.
+//! up: KeyW ArrowUp PadDPadUp LeftStickUp
+//! undo: KeyZ PadWest
+//! quit: KeyQ
+//! ```
+//!
+//! Commands are named as in replays. A command left out of the file has
+//! nothing bound to it. The same input may be bound to more than one
+//! command; [`Bindings::conflicts`] finds those, and the first command in
+//! [`COMMANDS`] order wins until they are sorted out.
+
+use std::fmt;
+
+use crate::replay::{command_name, parse_command};
+use crate::simulation::{Command, MoveDirection};
+
+/// Every command that can be bound, in the order they are listed.
+pub const COMMANDS: [Command; 8] = [
+    Command::Move(MoveDirection::Up),
+    Command::Move(MoveDirection::Down),
+    Command::Move(MoveDirection::Left),
+    Command::Move(MoveDirection::Right),
+    Command::Undo,
+    Command::Redo,
+    Command::Reset,
+    Command::Quit,
+];
+
+/// The inputs bound to each of [`COMMANDS`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bindings {
+    inputs: [Vec<String>; COMMANDS.len()],
+}
+
+impl Default for Bindings {
+    /// WASD and the arrow keys, and the d-pad and left stick on a gamepad.
+    fn default() -> Self {
+        Bindings::parse(
+            "up: KeyW ArrowUp PadDPadUp LeftStickUp
+            down: KeyS ArrowDown PadDPadDown LeftStickDown
+            left: KeyA ArrowLeft PadDPadLeft LeftStickLeft
+            right: KeyD ArrowRight PadDPadRight LeftStickRight
+            undo: KeyZ PadWest
+            redo: KeyY PadNorth
+            reset: KeyR PadSelect
+            quit: KeyQ",
+        )
+        .expect("default bindings parse")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindingsErrorKind {
+    Malformed,
+    UnknownCommand(String),
+}
+
+/// A problem with a bindings file, pointing at the 1-based line it was
+/// found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BindingsError {
+    pub line: usize,
+    pub kind: BindingsErrorKind,
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.line)?;
+        match &self.kind {
+            BindingsErrorKind::Malformed => write!(f, "expected `<command>: <input>...`"),
+            BindingsErrorKind::UnknownCommand(name) => write!(f, "unknown command `{name}`"),
+        }
+    }
+}
+
+impl std::error::Error for BindingsError {}
+
+/// An input bound to more than one command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub input: String,
+    pub commands: Vec<Command>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.commands.iter().map(|c| command_name(*c)).collect();
+        write!(f, "{} is bound to {}", self.input, names.join(" and "))
+    }
+}
+
+impl Bindings {
+    pub fn parse(source: &str) -> Result<Bindings, BindingsError> {
+        let mut bindings = Bindings {
+            inputs: Default::default(),
+        };
+        for (line_no, line) in source.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |kind| BindingsError {
+                line: line_no,
+                kind,
+            };
+            let (name, inputs) = line
+                .split_once(':')
+                .ok_or_else(|| error(BindingsErrorKind::Malformed))?;
+            let name = name.trim();
+            let command = parse_command(name)
+                .ok_or_else(|| error(BindingsErrorKind::UnknownCommand(name.to_string())))?;
+            for input in inputs.split_whitespace() {
+                bindings.bind(command, input);
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Writes the bindings out, one line for every command.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for command in COMMANDS {
+            let inputs = self.inputs(command);
+            out += command_name(command);
+            out += ":";
+            for input in inputs {
+                out += " ";
+                out += input;
+            }
+            out += "\n";
+        }
+        out
+    }
+
+    /// The inputs bound to `command`, in the order they were bound.
+    pub fn inputs(&self, command: Command) -> &[String] {
+        index(command).map_or(&[], |index| &self.inputs[index])
+    }
+
+    /// The command `input` sends, if it is bound to any.
+    pub fn command(&self, input: &str) -> Option<Command> {
+        COMMANDS
+            .into_iter()
+            .find(|command| self.inputs(*command).iter().any(|bound| bound == input))
+    }
+
+    /// Binds `input` to `command` as well as whatever it is already bound
+    /// to.
+    pub fn bind(&mut self, command: Command, input: &str) {
+        let Some(index) = index(command) else {
+            return;
+        };
+        let inputs = &mut self.inputs[index];
+        if !inputs.iter().any(|bound| bound == input) {
+            inputs.push(input.to_string());
+        }
+    }
+
+    pub fn unbind(&mut self, command: Command, input: &str) {
+        if let Some(index) = index(command) {
+            self.inputs[index].retain(|bound| bound != input);
+        }
+    }
+
+    /// Every input bound to more than one command, in the order they are
+    /// first bound.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts: Vec<Conflict> = vec![];
+        for command in COMMANDS {
+            for input in self.inputs(command) {
+                if conflicts.iter().any(|conflict| conflict.input == *input) {
+                    continue;
+                }
+                let commands: Vec<Command> = COMMANDS
+                    .into_iter()
+                    .filter(|other| self.inputs(*other).contains(input))
+                    .collect();
+                if commands.len() > 1 {
+                    conflicts.push(Conflict {
+                        input: input.clone(),
+                        commands,
+                    });
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+fn index(command: Command) -> Option<usize> {
+    COMMANDS.iter().position(|c| *c == command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bindings() {
+        let bindings = Bindings::parse(
+            "# comment
+            up: KeyW ArrowUp
+
+            undo: KeyZ",
+        )
+        .unwrap();
+        assert_eq!(
+            bindings.inputs(Command::Move(MoveDirection::Up)),
+            ["KeyW", "ArrowUp"]
+        );
+        assert_eq!(bindings.command("KeyZ"), Some(Command::Undo));
+        assert_eq!(bindings.command("KeyQ"), None);
+        assert!(bindings.inputs(Command::Quit).is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        let err = Bindings::parse("up: KeyW\nKeyS").unwrap_err();
+        assert_eq!((err.line, err.kind), (2, BindingsErrorKind::Malformed));
+        let err = Bindings::parse("jump: Space").unwrap_err();
+        assert_eq!(
+            err.kind,
+            BindingsErrorKind::UnknownCommand("jump".to_string())
+        );
+        assert_eq!(err.to_string(), "1: unknown command `jump`");
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let bindings = Bindings::default();
+        assert_eq!(Bindings::parse(&bindings.to_text()).unwrap(), bindings);
+    }
+
+    #[test]
+    fn bind_and_unbind() {
+        let mut bindings = Bindings::parse("").unwrap();
+        bindings.bind(Command::Redo, "KeyY");
+        bindings.bind(Command::Redo, "KeyY");
+        assert_eq!(bindings.inputs(Command::Redo), ["KeyY"]);
+        bindings.unbind(Command::Redo, "KeyY");
+        assert!(bindings.inputs(Command::Redo).is_empty());
+    }
+
+    #[test]
+    fn finds_conflicts() {
+        let bindings = Bindings::parse("undo: KeyZ\nreset: KeyZ KeyR\nquit: KeyR").unwrap();
+        let conflicts = bindings.conflicts();
+        assert_eq!(
+            conflicts,
+            [
+                Conflict {
+                    input: "KeyZ".to_string(),
+                    commands: vec![Command::Undo, Command::Reset],
+                },
+                Conflict {
+                    input: "KeyR".to_string(),
+                    commands: vec![Command::Reset, Command::Quit],
+                },
+            ]
+        );
+        // The first command in `COMMANDS` order wins.
+        assert_eq!(bindings.command("KeyZ"), Some(Command::Undo));
+        assert!(Bindings::default().conflicts().is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+//! Fingerprints of the simulation, for catching games that play out
+//! differently from how they were recorded.
+//!
+//! A [`Checksum`] hashes each part of a [`Simulation`] separately, so when two
+//! runs disagree it can also say which parts differ, and [`values`] writes
+//! each part out so a [`Divergence`] can show how they differ. Hashing uses FNV-1a over
+//! little-endian bytes, with `usize` and `isize` widened to 64 bits, so the
+//! same state checksums the same on native and wasm builds and across Rust
+//! versions.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::simulation::{GameState, Simulation};
+
+/// The parts of the simulation a [`Checksum`] covers, in order.
+pub const FIELDS: [&str; 13] = [
+    "current_direction",
+    "desired_direction",
+    "player_position",
+    "scores",
+    "ghosts",
+    "lives",
+    "level",
+    "moves",
+    "pushes",
+    "game_over",
+    "level_complete",
+    "tilemap",
+    "rng",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Checksum(pub [u64; FIELDS.len()]);
+
+impl Checksum {
+    pub fn of(sim: &Simulation) -> Checksum {
+        let GameState {
+            current_direction,
+            desired_direction,
+            player_position,
+            scores,
+            ghosts,
+            lives,
+            level,
+            moves,
+            pushes,
+            game_over,
+            level_complete,
+        } = &sim.state;
+        Checksum([
+            fnv(current_direction),
+            fnv(desired_direction),
+            fnv(player_position),
+            fnv(scores),
+            fnv(ghosts),
+            fnv(lives),
+            fnv(level),
+            fnv(moves),
+            fnv(pushes),
+            fnv(game_over),
+            fnv(level_complete),
+            fnv(&sim.tilemap),
+            fnv(&sim.rng),
+        ])
+    }
+
+    /// Indices into [`FIELDS`] of the parts that differ between `self` and
+    /// `other`.
+    pub fn diff(&self, other: &Checksum) -> Vec<usize> {
+        (0..FIELDS.len())
+            .filter(|&field| self.0[field] != other.0[field])
+            .collect()
+    }
+}
+
+/// Each of the [`FIELDS`] of `sim` written out on one line, in order.
+pub fn values(sim: &Simulation) -> [String; FIELDS.len()] {
+    let GameState {
+        current_direction,
+        desired_direction,
+        player_position,
+        scores,
+        ghosts,
+        lives,
+        level,
+        moves,
+        pushes,
+        game_over,
+        level_complete,
+    } = &sim.state;
+    [
+        format!("{current_direction:?}"),
+        format!("{desired_direction:?}"),
+        format!("{player_position:?}"),
+        format!("{scores:?}"),
+        format!("{ghosts:?}"),
+        format!("{lives:?}"),
+        format!("{level:?}"),
+        format!("{moves:?}"),
+        format!("{pushes:?}"),
+        format!("{game_over:?}"),
+        format!("{level_complete:?}"),
+        format!("{:?}", sim.tilemap),
+        format!("{:?}", sim.rng),
+    ]
+}
+
+/// The first tick at which a replay stopped matching its recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub tick: u32,
+    pub fields: Vec<FieldDiff>,
+}
+
+/// One of the [`FIELDS`] that differs, as it was recorded and as it played
+/// back. `expected` is missing if the recording didn't keep the value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub expected: Option<String>,
+    pub actual: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "diverged at tick {}:", self.tick)?;
+        for diff in &self.fields {
+            let expected = diff.expected.as_deref().unwrap_or("(not recorded)");
+            write!(
+                f,
+                "\n  {}: expected {expected}, got {}",
+                diff.field, diff.actual
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+fn fnv(value: &impl Hash) -> u64 {
+    let mut hasher = Fnv(0xcbf2_9ce4_8422_2325);
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+struct Fnv(u64);
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_i32(&mut self, n: i32) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_isize(&mut self, n: isize) {
+        self.write_u64(n as i64 as u64);
+    }
+}
@@ -0,0 +1,415 @@
+//! Ghost movement.
+//!
+//! Every ghost heads for a target tile, choosing at each step the open
+//! direction that gets it closest without turning back, like the arcade
+//! game. Where the target is depends on the ghost's [`Personality`] and on
+//! whether the ghosts are currently in [`GhostMode::Scatter`] or
+//! [`GhostMode::Chase`]. The two modes alternate on a per-level schedule and
+//! every ghost turns around when the mode changes.
+//!
+//! A power pellet frightens the ghosts for a while: the schedule pauses, and
+//! they flee from the player and can be eaten. An eaten ghost goes back to
+//! its spawn as eyes before it rejoins the chase. How fast a ghost goes in
+//! each state is down to [`Speeds`](crate::speed::Speeds).
+
+use crate::simulation::{get_next_position, GameEvent, MoveDirection, Simulation};
+use crate::speed::moves_on;
+use crate::tilemap::{Tilemap, Wrap};
+
+/// Simulation ticks per second of game time.
+pub const TICKS_PER_SECOND: i32 = 4;
+
+/// How close the shy ghost lets the player get before it retreats.
+const SHY_DISTANCE: i32 = 8;
+
+#[derive(Clone, PartialEq, Debug, Hash, Eq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Personality {
+    /// Targets the player's tile.
+    Chaser,
+    /// Targets four tiles ahead of the player.
+    Ambusher,
+    /// Targets the chaser's position mirrored through the tile two ahead of
+    /// the player, so it closes in from the other side.
+    Flanker,
+    /// Chases like the chaser until it gets close, then retreats to its
+    /// corner.
+    Shy,
+}
+
+impl Personality {
+    /// Personalities handed out to the spawns of a level, in order.
+    pub const ORDER: [Personality; 4] = [
+        Personality::Chaser,
+        Personality::Ambusher,
+        Personality::Flanker,
+        Personality::Shy,
+    ];
+}
+
+#[derive(Clone, PartialEq, Debug, Hash, Eq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GhostMode {
+    Scatter,
+    Chase,
+}
+
+#[derive(Clone, PartialEq, Debug, Hash, Eq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GhostState {
+    Active,
+    Frightened,
+    /// Eaten, and heading back to its spawn.
+    Eyes,
+}
+
+#[derive(Clone, PartialEq, Debug, Hash, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ghost {
+    pub personality: Personality,
+    pub pos: (i32, i32),
+    pub direction: MoveDirection,
+    pub spawn: (i32, i32),
+    pub state: GhostState,
+}
+
+/// All ghosts of a game and the scatter/chase phase they are in.
+#[derive(Clone, PartialEq, Debug, Hash, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ghosts {
+    pub list: Vec<Ghost>,
+    pub mode: GhostMode,
+    pub phase: usize,
+    pub phase_ticks: i32,
+    /// Ticks of fright left from the last power pellet.
+    pub frightened_ticks: i32,
+    /// Ghosts eaten since the last power pellet, which sets what the next
+    /// one is worth.
+    pub eaten_in_fright: u32,
+    /// Ticks played so far, which decides who sits out a tick to move at
+    /// their speed.
+    pub ticks: i32,
+}
+
+impl Ghosts {
+    /// One ghost per spawn, with personalities given out in
+    /// [`Personality::ORDER`].
+    pub fn new(spawns: &[(i32, i32)]) -> Self {
+        Ghosts {
+            list: spawns
+                .iter()
+                .zip(Personality::ORDER.iter().cycle())
+                .map(|(&pos, &personality)| Ghost {
+                    personality,
+                    pos,
+                    direction: MoveDirection::Left,
+                    spawn: pos,
+                    state: GhostState::Active,
+                })
+                .collect(),
+            mode: GhostMode::Scatter,
+            phase: 0,
+            phase_ticks: 0,
+            frightened_ticks: 0,
+            eaten_in_fright: 0,
+            ticks: 0,
+        }
+    }
+
+    /// Puts every ghost back on its spawn and chasing again, after the player
+    /// is caught. The scatter/chase schedule carries on where it was.
+    pub fn send_home(&mut self) {
+        for ghost in &mut self.list {
+            ghost.pos = ghost.spawn;
+            ghost.direction = MoveDirection::Left;
+            ghost.state = GhostState::Active;
+        }
+        self.frightened_ticks = 0;
+        self.eaten_in_fright = 0;
+    }
+
+    /// Indices of the ghosts on `pos` that can still touch the player.
+    pub fn at(&self, pos: (i32, i32)) -> Vec<usize> {
+        self.list
+            .iter()
+            .enumerate()
+            .filter(|(_, ghost)| ghost.pos == pos && ghost.state != GhostState::Eyes)
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+/// Points for the `n`th ghost eaten on one power pellet, counting from 0.
+pub fn ghost_points(n: u32) -> i32 {
+    200 << n.min(3)
+}
+
+/// How long each phase of `level` lasts, in seconds. The phase after the last
+/// one is chase until the level ends.
+pub fn phase_schedule(level: i32) -> &'static [(GhostMode, i32)] {
+    use GhostMode::*;
+    match level {
+        1 => &[
+            (Scatter, 7),
+            (Chase, 20),
+            (Scatter, 7),
+            (Chase, 20),
+            (Scatter, 5),
+            (Chase, 20),
+            (Scatter, 5),
+        ],
+        2..=4 => &[
+            (Scatter, 7),
+            (Chase, 20),
+            (Scatter, 7),
+            (Chase, 20),
+            (Scatter, 5),
+            (Chase, 1033),
+        ],
+        _ => &[
+            (Scatter, 5),
+            (Chase, 20),
+            (Scatter, 5),
+            (Chase, 20),
+            (Scatter, 5),
+            (Chase, 1037),
+        ],
+    }
+}
+
+/// Frightens every ghost that isn't already eaten for `seconds`, turning them
+/// around.
+pub(crate) fn frighten(sim: &mut Simulation, seconds: i32, events: &mut Vec<GameEvent>) {
+    let ghosts = &mut sim.state.ghosts;
+    ghosts.frightened_ticks = seconds * TICKS_PER_SECOND;
+    ghosts.eaten_in_fright = 0;
+    for ghost in &mut ghosts.list {
+        if ghost.state != GhostState::Eyes {
+            ghost.state = GhostState::Frightened;
+            ghost.direction = ghost.direction.opposite();
+        }
+    }
+    events.push(GameEvent::GhostsFrightened);
+}
+
+/// Counts down the fright, or while there is none moves on to the next
+/// scatter/chase phase once the current one has run out, turning every ghost
+/// around.
+pub(crate) fn advance_phase(sim: &mut Simulation, events: &mut Vec<GameEvent>) {
+    let schedule = phase_schedule(sim.state.level);
+    let ghosts = &mut sim.state.ghosts;
+    if ghosts.frightened_ticks > 0 {
+        ghosts.frightened_ticks -= 1;
+        if ghosts.frightened_ticks == 0 {
+            for ghost in &mut ghosts.list {
+                if ghost.state == GhostState::Frightened {
+                    ghost.state = GhostState::Active;
+                }
+            }
+            events.push(GameEvent::FrightEnded);
+        }
+        return;
+    }
+    let Some(&(_, seconds)) = schedule.get(ghosts.phase) else {
+        return;
+    };
+    ghosts.phase_ticks += 1;
+    if ghosts.phase_ticks < seconds * TICKS_PER_SECOND {
+        return;
+    }
+
+    ghosts.phase += 1;
+    ghosts.phase_ticks = 0;
+    ghosts.mode = schedule
+        .get(ghosts.phase)
+        .map_or(GhostMode::Chase, |(mode, _)| *mode);
+    for ghost in &mut ghosts.list {
+        ghost.direction = ghost.direction.opposite();
+    }
+    events.push(GameEvent::GhostModeChanged(ghosts.mode));
+}
+
+/// Moves every ghost one tile and resolves any ghost that ends up touching
+/// the player, including when the two swap tiles this tick. `player_from` is
+/// where the player stood before this tick's move. Ghosts skip ticks to
+/// move at their [`Simulation::ghost_speed`].
+pub(crate) fn ghosts_move(
+    sim: &mut Simulation,
+    player_from: (i32, i32),
+    events: &mut Vec<GameEvent>,
+) {
+    let player = sim.state.player_position;
+    let heading = sim.state.current_direction;
+    let chaser = sim
+        .state
+        .ghosts
+        .list
+        .iter()
+        .find(|ghost| ghost.personality == Personality::Chaser)
+        .map(|ghost| ghost.pos);
+    let corners = corners(&sim.tilemap);
+    let ticks = sim.state.ghosts.ticks;
+
+    for index in 0..sim.state.ghosts.list.len() {
+        if !moves_on(ticks, sim.ghost_speed(index)) {
+            continue;
+        }
+        let ghost = &sim.state.ghosts.list[index];
+        let (target, flee) = match (ghost.state, sim.state.ghosts.mode) {
+            (GhostState::Eyes, _) => (ghost.spawn, false),
+            (GhostState::Frightened, _) => (player, true),
+            (GhostState::Active, GhostMode::Scatter) => {
+                (scatter_target(ghost.personality, corners), false)
+            }
+            (GhostState::Active, GhostMode::Chase) => {
+                (chase_target(ghost, player, heading, chaser, corners), false)
+            }
+        };
+        let from = ghost.pos;
+        let direction =
+            choose_direction(&sim.tilemap, sim.wrap, from, ghost.direction, target, flee);
+        let to = get_next_position(&sim.tilemap, sim.wrap, from, Some(direction));
+
+        let ghost = &mut sim.state.ghosts.list[index];
+        ghost.direction = direction;
+        if to == from {
+            continue;
+        }
+        ghost.pos = to;
+        events.push(GameEvent::GhostMoved {
+            ghost: index,
+            from,
+            to,
+            direction,
+        });
+
+        if ghost.state == GhostState::Eyes {
+            if to == ghost.spawn {
+                ghost.state = GhostState::Active;
+                events.push(GameEvent::GhostRevived { ghost: index });
+            }
+            continue;
+        }
+        if (to == player || (to == player_from && from == player))
+            && touch_player(sim, index, events)
+        {
+            return;
+        }
+    }
+}
+
+/// The player and ghost `index` are on the same tile: a frightened ghost is
+/// eaten, and an active one catches the player, costing a life. Returns
+/// whether the player was caught.
+pub(crate) fn touch_player(
+    sim: &mut Simulation,
+    index: usize,
+    events: &mut Vec<GameEvent>,
+) -> bool {
+    let ghosts = &mut sim.state.ghosts;
+    match ghosts.list[index].state {
+        GhostState::Frightened => {
+            let points = ghost_points(ghosts.eaten_in_fright);
+            ghosts.eaten_in_fright += 1;
+            ghosts.list[index].state = GhostState::Eyes;
+            sim.state.scores.current_score += points;
+            events.push(GameEvent::GhostEaten {
+                ghost: index,
+                points,
+            });
+            false
+        }
+        GhostState::Active => {
+            sim.lose_life(events);
+            true
+        }
+        GhostState::Eyes => false,
+    }
+}
+
+/// Top left, top right, bottom left and bottom right of the map.
+fn corners(tilemap: &Tilemap) -> [(i32, i32); 4] {
+    let bottom = tilemap.height() as i32 - 1;
+    let right = tilemap.width(0) as i32 - 1;
+    [(0, 0), (right, 0), (0, bottom), (right, bottom)]
+}
+
+fn scatter_target(personality: Personality, corners: [(i32, i32); 4]) -> (i32, i32) {
+    match personality {
+        Personality::Chaser => corners[1],
+        Personality::Ambusher => corners[0],
+        Personality::Flanker => corners[3],
+        Personality::Shy => corners[2],
+    }
+}
+
+fn chase_target(
+    ghost: &Ghost,
+    player: (i32, i32),
+    heading: Option<MoveDirection>,
+    chaser: Option<(i32, i32)>,
+    corners: [(i32, i32); 4],
+) -> (i32, i32) {
+    let ahead = |tiles: i32| {
+        let (dx, dy) = heading.map_or((0, 0), offset);
+        (player.0 + dx * tiles, player.1 + dy * tiles)
+    };
+    match ghost.personality {
+        Personality::Chaser => player,
+        Personality::Ambusher => ahead(4),
+        Personality::Flanker => {
+            let pivot = ahead(2);
+            let chaser = chaser.unwrap_or(ghost.pos);
+            (2 * pivot.0 - chaser.0, 2 * pivot.1 - chaser.1)
+        }
+        Personality::Shy => {
+            if distance_squared(ghost.pos, player) > SHY_DISTANCE * SHY_DISTANCE {
+                player
+            } else {
+                scatter_target(Personality::Shy, corners)
+            }
+        }
+    }
+}
+
+/// The open direction, other than straight back, that leaves the ghost
+/// closest to `target`, or furthest from it when fleeing. Ties go to up,
+/// left, down, right in that order. A ghost in a dead end turns back.
+fn choose_direction(
+    tilemap: &Tilemap,
+    wrap: Wrap,
+    pos: (i32, i32),
+    current: MoveDirection,
+    target: (i32, i32),
+    flee: bool,
+) -> MoveDirection {
+    [
+        MoveDirection::Up,
+        MoveDirection::Left,
+        MoveDirection::Down,
+        MoveDirection::Right,
+    ]
+    .into_iter()
+    .filter(|dir| *dir != current.opposite())
+    .filter_map(|dir| {
+        let next = get_next_position(tilemap, wrap, pos, Some(dir));
+        let distance = distance_squared(next, target);
+        (next != pos && !tilemap.is_wall(next))
+            .then_some((if flee { -distance } else { distance }, dir))
+    })
+    .min_by_key(|(distance, _)| *distance)
+    .map_or(current.opposite(), |(_, dir)| dir)
+}
+
+fn offset(direction: MoveDirection) -> (i32, i32) {
+    match direction {
+        MoveDirection::Up => (0, -1),
+        MoveDirection::Down => (0, 1),
+        MoveDirection::Left => (-1, 0),
+        MoveDirection::Right => (1, 0),
+    }
+}
+
+fn distance_squared(a: (i32, i32), b: (i32, i32)) -> i32 {
+    (a.0 - b.0).pow(2) + (a.1 - b.1).pow(2)
+}
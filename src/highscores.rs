@@ -0,0 +1,172 @@
+//! The best scores ever reached, kept between runs.
+//!
+//! [`HighScores`] is the top-ten table itself. Where it lives is up to a
+//! [`ScoreStorage`]: with the `serde` feature the table is stored as JSON, in
+//! a file on native builds ([`FileStorage`]) or in the browser's
+//! `localStorage` on wasm ([`LocalStorage`]). [`NoStorage`] keeps nothing.
+
+use std::fmt;
+
+/// How many entries the table keeps.
+pub const TABLE_SIZE: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HighScore {
+    pub name: String,
+    pub score: i32,
+    /// When the score was set, in seconds since the Unix epoch.
+    pub date: u64,
+    pub level: i32,
+    /// The seed the maze was laid out with, when there was one.
+    pub seed: Option<u64>,
+}
+
+/// The top [`TABLE_SIZE`] scores, best first.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HighScores {
+    entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    pub fn entries(&self) -> &[HighScore] {
+        &self.entries
+    }
+
+    /// The best score in the table, or 0 when it is empty.
+    pub fn best(&self) -> i32 {
+        self.entries.first().map_or(0, |entry| entry.score)
+    }
+
+    /// Adds `entry` if it makes the table, returning its 0-based rank. Ties
+    /// rank below the scores already there.
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        let rank = self
+            .entries
+            .iter()
+            .position(|other| other.score < entry.score)
+            .unwrap_or(self.entries.len());
+        if rank >= TABLE_SIZE {
+            return None;
+        }
+        self.entries.insert(rank, entry);
+        self.entries.truncate(TABLE_SIZE);
+        Some(rank)
+    }
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(std::io::Error),
+    /// The stored table couldn't be read or written as JSON.
+    Format(String),
+    /// There is nowhere to store the table, e.g. the browser blocks
+    /// `localStorage`.
+    Unavailable,
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(err) => write!(f, "{err}"),
+            StorageError::Format(err) => write!(f, "bad high score table: {err}"),
+            StorageError::Unavailable => write!(f, "no storage for high scores"),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<std::io::Error> for StorageError {
+    fn from(err: std::io::Error) -> Self {
+        StorageError::Io(err)
+    }
+}
+
+/// Somewhere the high score table can be kept between runs.
+pub trait ScoreStorage {
+    /// The stored table. Nothing having been stored yet is an empty table,
+    /// not an error.
+    fn load(&self) -> Result<HighScores, StorageError>;
+    fn save(&self, scores: &HighScores) -> Result<(), StorageError>;
+}
+
+/// Keeps nothing: every run starts with an empty table.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoStorage;
+
+impl ScoreStorage for NoStorage {
+    fn load(&self) -> Result<HighScores, StorageError> {
+        Ok(HighScores::default())
+    }
+
+    fn save(&self, _scores: &HighScores) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+/// Stores the table as JSON in a file.
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+#[derive(Debug, Clone)]
+pub struct FileStorage {
+    pub path: std::path::PathBuf,
+}
+
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+impl ScoreStorage for FileStorage {
+    fn load(&self) -> Result<HighScores, StorageError> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(json) => from_json(&json),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(HighScores::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn save(&self, scores: &HighScores) -> Result<(), StorageError> {
+        Ok(std::fs::write(&self.path, to_json(scores)?)?)
+    }
+}
+
+/// Stores the table as JSON under `key` in the browser's `localStorage`.
+#[cfg(all(feature = "serde", target_arch = "wasm32"))]
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    pub key: String,
+}
+
+#[cfg(all(feature = "serde", target_arch = "wasm32"))]
+impl LocalStorage {
+    fn storage() -> Result<web_sys::Storage, StorageError> {
+        web_sys::window()
+            .and_then(|window| window.local_storage().ok().flatten())
+            .ok_or(StorageError::Unavailable)
+    }
+}
+
+#[cfg(all(feature = "serde", target_arch = "wasm32"))]
+impl ScoreStorage for LocalStorage {
+    fn load(&self) -> Result<HighScores, StorageError> {
+        match Self::storage()?.get_item(&self.key) {
+            Ok(Some(json)) => from_json(&json),
+            Ok(None) => Ok(HighScores::default()),
+            Err(_) => Err(StorageError::Unavailable),
+        }
+    }
+
+    fn save(&self, scores: &HighScores) -> Result<(), StorageError> {
+        Self::storage()?
+            .set_item(&self.key, &to_json(scores)?)
+            .map_err(|_| StorageError::Unavailable)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn from_json(json: &str) -> Result<HighScores, StorageError> {
+    serde_json::from_str(json).map_err(|err| StorageError::Format(err.to_string()))
+}
+
+#[cfg(feature = "serde")]
+fn to_json(scores: &HighScores) -> Result<String, StorageError> {
+    serde_json::to_string_pretty(scores).map_err(|err| StorageError::Format(err.to_string()))
+}
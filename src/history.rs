@@ -0,0 +1,303 @@
+//! Undo and redo.
+//!
+//! Rather than cloning the whole simulation every move, each step keeps the
+//! [`GameEvent`]s it produced. Those already say exactly what changed, so
+//! undoing a step is replaying its events backwards and redoing it is
+//! replaying them forwards. The only extra state kept is the player's
+//! heading and queued turn and the handful of ghosts on either side of the
+//! step.
+
+use crate::ghost::Ghosts;
+use crate::simulation::POWER_PELLET_POINTS;
+use crate::simulation::{GameEvent, MoveDirection, Simulation};
+use crate::tilemap::{BOX, PELLET, PLAYER, POWER_PELLET};
+
+/// The player's heading, their queued turn and the ghosts, on one side of a
+/// step.
+pub(crate) type Headings = (Option<MoveDirection>, Option<MoveDirection>, Ghosts);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Delta {
+    before: Headings,
+    after: Headings,
+    events: Vec<GameEvent>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct History {
+    undo: Vec<Delta>,
+    redo: Vec<Delta>,
+}
+
+impl History {
+    /// Remembers one step. Steps that changed nothing are dropped, and any
+    /// new step forgets what could have been redone.
+    pub(crate) fn record(&mut self, before: Headings, after: Headings, events: &[GameEvent]) {
+        let events: Vec<GameEvent> = events
+            .iter()
+            .copied()
+            .filter(|event| !matches!(event, GameEvent::GameOver | GameEvent::Reset))
+            .collect();
+        if events.is_empty() {
+            return;
+        }
+        self.undo.push(Delta {
+            before,
+            after,
+            events,
+        });
+        self.redo.clear();
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+/// Reverts the last recorded step, pushing the events a frontend needs to
+/// bring its view back in line.
+pub(crate) fn undo(sim: &mut Simulation, events: &mut Vec<GameEvent>) {
+    let Some(delta) = sim.history.undo.pop() else {
+        return;
+    };
+    for event in delta.events.iter().rev() {
+        revert(sim, *event, events);
+    }
+    sim.state.current_direction = delta.before.0;
+    sim.state.desired_direction = delta.before.1;
+    sim.state.ghosts = delta.before.2.clone();
+    sim.history.redo.push(delta);
+}
+
+/// Applies the last undone step again.
+pub(crate) fn redo(sim: &mut Simulation, events: &mut Vec<GameEvent>) {
+    let Some(delta) = sim.history.redo.pop() else {
+        return;
+    };
+    for event in &delta.events {
+        apply(sim, *event);
+        events.push(*event);
+    }
+    sim.state.current_direction = delta.after.0;
+    sim.state.desired_direction = delta.after.1;
+    sim.state.ghosts = delta.after.2.clone();
+    sim.history.undo.push(delta);
+}
+
+fn apply(sim: &mut Simulation, event: GameEvent) {
+    match event {
+        GameEvent::PelletEaten { pos } => {
+            sim.tilemap.remove(pos, PELLET);
+            sim.state.scores.current_score += 1;
+        }
+        GameEvent::PowerPelletEaten { pos } => {
+            sim.tilemap.remove(pos, POWER_PELLET);
+            sim.state.scores.current_score += POWER_PELLET_POINTS;
+        }
+        GameEvent::PelletRestored { pos, glyph } => {
+            sim.tilemap.cell_mut(pos).push(glyph);
+            sim.state.scores.current_score -= pellet_points(glyph);
+        }
+        GameEvent::PlayerMoved { from, to, .. } => {
+            sim.tilemap.remove(from, PLAYER);
+            sim.tilemap.cell_mut(to).push(PLAYER);
+            sim.state.player_position = to;
+            sim.state.moves += 1;
+        }
+        GameEvent::BoxPushed { from, to } => {
+            sim.tilemap.remove(from, BOX);
+            sim.tilemap.cell_mut(to).push(BOX);
+            sim.state.pushes += 1;
+        }
+        GameEvent::GhostEaten { points, .. } => sim.state.scores.current_score += points,
+        GameEvent::LifeLost { .. } => sim.state.lives -= 1,
+        GameEvent::PlayerRespawned { from, to } => {
+            sim.tilemap.remove(from, PLAYER);
+            sim.tilemap.cell_mut(to).push(PLAYER);
+            sim.state.player_position = to;
+        }
+        GameEvent::ExtraLife => sim.state.lives += 1,
+        GameEvent::LevelComplete => sim.state.level_complete = true,
+        GameEvent::GhostMoved { .. }
+        | GameEvent::GhostModeChanged(_)
+        | GameEvent::GhostsFrightened
+        | GameEvent::FrightEnded
+        | GameEvent::GhostRevived { .. }
+        | GameEvent::LevelStarted
+        | GameEvent::GameOver
+        | GameEvent::Reset => (),
+    }
+}
+
+fn revert(sim: &mut Simulation, event: GameEvent, events: &mut Vec<GameEvent>) {
+    match event {
+        GameEvent::PelletEaten { pos } => {
+            sim.tilemap.cell_mut(pos).push(PELLET);
+            sim.state.scores.current_score -= 1;
+            events.push(GameEvent::PelletRestored { pos, glyph: PELLET });
+        }
+        GameEvent::PowerPelletEaten { pos } => {
+            sim.tilemap.cell_mut(pos).push(POWER_PELLET);
+            sim.state.scores.current_score -= POWER_PELLET_POINTS;
+            events.push(GameEvent::PelletRestored {
+                pos,
+                glyph: POWER_PELLET,
+            });
+        }
+        GameEvent::PelletRestored { pos, glyph } => {
+            sim.tilemap.remove(pos, glyph);
+            sim.state.scores.current_score += pellet_points(glyph);
+            events.push(if glyph == POWER_PELLET {
+                GameEvent::PowerPelletEaten { pos }
+            } else {
+                GameEvent::PelletEaten { pos }
+            });
+        }
+        GameEvent::PlayerMoved {
+            from,
+            to,
+            direction,
+        } => {
+            sim.tilemap.remove(to, PLAYER);
+            sim.tilemap.cell_mut(from).push(PLAYER);
+            sim.state.player_position = from;
+            sim.state.moves -= 1;
+            events.push(GameEvent::PlayerMoved {
+                from: to,
+                to: from,
+                direction,
+            });
+        }
+        GameEvent::BoxPushed { from, to } => {
+            sim.tilemap.remove(to, BOX);
+            sim.tilemap.cell_mut(from).push(BOX);
+            sim.state.pushes -= 1;
+            events.push(GameEvent::BoxPushed { from: to, to: from });
+        }
+        GameEvent::GhostMoved {
+            ghost,
+            from,
+            to,
+            direction,
+        } => events.push(GameEvent::GhostMoved {
+            ghost,
+            from: to,
+            to: from,
+            direction,
+        }),
+        GameEvent::GhostEaten { points, .. } => sim.state.scores.current_score -= points,
+        GameEvent::LifeLost { .. } => sim.state.lives += 1,
+        GameEvent::PlayerRespawned { from, to } => {
+            sim.tilemap.remove(to, PLAYER);
+            sim.tilemap.cell_mut(from).push(PLAYER);
+            sim.state.player_position = from;
+            events.push(GameEvent::PlayerRespawned { from: to, to: from });
+        }
+        GameEvent::ExtraLife => sim.state.lives -= 1,
+        GameEvent::LevelComplete => sim.state.level_complete = false,
+        GameEvent::GhostModeChanged(_)
+        | GameEvent::GhostsFrightened
+        | GameEvent::FrightEnded
+        | GameEvent::GhostRevived { .. }
+        | GameEvent::LevelStarted
+        | GameEvent::GameOver
+        | GameEvent::Reset => (),
+    }
+}
+
+fn pellet_points(glyph: char) -> i32 {
+    if glyph == POWER_PELLET {
+        POWER_PELLET_POINTS
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Level;
+    use crate::simulation::Command;
+
+    const RIGHT: Command = Command::Move(MoveDirection::Right);
+
+    fn start(source: &str) -> Simulation {
+        Simulation::from_level(&Level::parse(source).unwrap(), 1)
+    }
+
+    #[test]
+    fn undoes_and_redoes_a_push() {
+        let mut sim = start("rules: sokoban\n---\n######\n#K$ .#\n######\n");
+        let pushed = sim.step(&[RIGHT]);
+        let after = (sim.state.clone(), sim.tilemap.clone());
+        assert!(sim.history().can_undo());
+
+        let events = sim.step(&[Command::Undo]);
+        assert_eq!(
+            events,
+            [
+                GameEvent::PlayerMoved {
+                    from: (2, 1),
+                    to: (1, 1),
+                    direction: MoveDirection::Right
+                },
+                GameEvent::BoxPushed {
+                    from: (3, 1),
+                    to: (2, 1)
+                },
+            ]
+        );
+        assert_eq!(sim.state.player_position, (1, 1));
+        assert_eq!((sim.state.moves, sim.state.pushes), (0, 0));
+        assert_eq!(sim.tilemap.cell((2, 1)), &vec![BOX]);
+        assert!(!sim.history().can_undo());
+        assert!(sim.history().can_redo());
+
+        assert_eq!(sim.step(&[Command::Redo]), pushed);
+        assert_eq!((sim.state.clone(), sim.tilemap.clone()), after);
+    }
+
+    #[test]
+    fn undoes_an_eaten_pellet() {
+        let mut sim = start("pellet_density: 0\n---\n#####\n#K• #\n#•###\n#####\n");
+        let mut steps = 0;
+        while sim.state.scores.current_score == 0 {
+            sim.step(if steps == 0 { &[RIGHT] } else { &[] });
+            steps += 1;
+        }
+        let events = sim.step(&[Command::Undo]);
+        assert!(events.contains(&GameEvent::PelletRestored {
+            pos: (2, 1),
+            glyph: PELLET
+        }));
+        assert_eq!(sim.state.scores.current_score, 0);
+        assert_eq!(sim.state.player_position, (1, 1));
+        assert_eq!(sim.pellets_left(), 2);
+        // The high score isn't taken back.
+        assert_eq!(sim.state.scores.high_score, 1);
+    }
+
+    #[test]
+    fn a_new_move_forgets_the_redo() {
+        let mut sim = start("rules: sokoban\n---\n#######\n#K$  .#\n#######\n");
+        sim.step(&[RIGHT]);
+        sim.step(&[Command::Undo]);
+        assert!(sim.history().can_redo());
+        sim.step(&[RIGHT]);
+        assert!(!sim.history().can_redo());
+        assert_eq!(sim.step(&[Command::Redo]), []);
+    }
+
+    #[test]
+    fn nothing_to_undo() {
+        let mut sim = start("rules: sokoban\n---\n#####\n#K$.#\n#####\n");
+        let before = sim.state.clone();
+        assert_eq!(sim.step(&[Command::Undo]), []);
+        assert_eq!(sim.step(&[Command::Redo]), []);
+        assert_eq!(sim.state, before);
+    }
+}
@@ -0,0 +1,645 @@
+//! Plain-text level files.
+//!
+//! A level is a `key: value` header, a `---` separator and then the maze,
+//! one row per line, drawn with the same glyphs the [`Tilemap`] uses:
+//!
+//! ```text
+//! name: Classic
+//! author: JamesZoft
+//! pellet_density: 0.75
+//! ---
+//! ___________________
+//! |        #        |
+//! ...
+//! ```
+//!
+//! An optional `ghost_spawn: x,y` line, which may be repeated, adds a ghost
+//! start on top of any `m` tiles. It has to be on a blank or pellet cell. Every blank cell gets a pellet with
+//! probability `pellet_density` when the level is turned into a [`Tilemap`].
+//! `O` tiles are power pellets, which frighten the ghosts for
+//! `frightened_time` seconds.
+//!
+//! The player gets `lives` lives, and one more on reaching a score of
+//! `extra_life` (`0` for never).
+//!
+//! `wrap: horizontal`, `vertical` or `both` joins up opposite edges of the
+//! maze so open cells on them lead round to the other side, and
+//! `tunnel_slowdown: true` makes ghosts crawl through such tunnels.
+//!
+//! `rules: sokoban` switches to [`Rules::Sokoban`]. Such levels never get
+//! pellets and may use `*` for a box on a goal and `+` for the player on a
+//! goal, since each cell of the file holds a single glyph. They need at least
+//! one box, and as many goals as boxes.
+
+use std::fmt;
+
+use rand::Rng;
+
+use crate::simulation::{
+    Rules, DEFAULT_EXTRA_LIFE_SCORE, DEFAULT_FRIGHTENED_SECONDS, DEFAULT_LIVES,
+};
+use crate::tilemap::{Tilemap, Wrap, BOX, GHOST, GOAL, PELLET, PLAYER, POWER_PELLET, VOID, WALLS};
+
+const SEPARATOR: &str = "---";
+const BOX_ON_GOAL: char = '*';
+const PLAYER_ON_GOAL: char = '+';
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Level {
+    pub name: String,
+    pub author: String,
+    pub pellet_density: f32,
+    /// How long a power pellet frightens the ghosts for, in seconds.
+    pub frightened_seconds: i32,
+    pub wrap: Wrap,
+    pub tunnel_slowdown: bool,
+    pub lives: i32,
+    /// The score that earns an extra life, or zero for none.
+    pub extra_life_score: i32,
+    pub rules: Rules,
+    pub player_spawn: (i32, i32),
+    pub ghost_spawns: Vec<(i32, i32)>,
+    pub(crate) grid: Vec<Vec<char>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LevelErrorKind {
+    MissingSeparator,
+    MalformedHeader,
+    UnknownKey(String),
+    InvalidValue {
+        key: String,
+        value: String,
+    },
+    EmptyMaze,
+    UnknownGlyph(char),
+    RaggedRow {
+        expected: usize,
+        found: usize,
+    },
+    MissingPlayerSpawn,
+    DuplicatePlayerSpawn,
+    SpawnOutOfBounds((i32, i32)),
+    /// A header spawn on a cell that already holds something else, such as
+    /// the player or a box.
+    SpawnOccupied {
+        spawn: (i32, i32),
+        glyph: char,
+    },
+    BoxGoalMismatch {
+        boxes: usize,
+        goals: usize,
+    },
+    /// A Sokoban level without a single box, which could never be solved.
+    NoBoxes,
+}
+
+/// A problem with a level file, pointing at the 1-based line and column it
+/// was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: LevelErrorKind,
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            LevelErrorKind::MissingSeparator => write!(f, "expected a `{SEPARATOR}` line"),
+            LevelErrorKind::MalformedHeader => write!(f, "expected `key: value`"),
+            LevelErrorKind::UnknownKey(key) => write!(f, "unknown header key `{key}`"),
+            LevelErrorKind::InvalidValue { key, value } => {
+                write!(f, "invalid value `{value}` for `{key}`")
+            }
+            LevelErrorKind::EmptyMaze => write!(f, "the maze has no rows"),
+            LevelErrorKind::UnknownGlyph(c) => write!(f, "unknown glyph `{c}`"),
+            LevelErrorKind::RaggedRow { expected, found } => {
+                write!(f, "row is {found} cells wide, expected {expected}")
+            }
+            LevelErrorKind::MissingPlayerSpawn => write!(f, "no `{PLAYER}` spawn in the maze"),
+            LevelErrorKind::DuplicatePlayerSpawn => write!(f, "more than one `{PLAYER}` spawn"),
+            LevelErrorKind::SpawnOutOfBounds((x, y)) => {
+                write!(f, "spawn {x},{y} is outside the maze or inside a wall")
+            }
+            LevelErrorKind::SpawnOccupied {
+                spawn: (x, y),
+                glyph,
+            } => write!(
+                f,
+                "spawn {x},{y} is on `{glyph}`, not a blank or pellet cell"
+            ),
+            LevelErrorKind::BoxGoalMismatch { boxes, goals } => {
+                write!(f, "{boxes} boxes but {goals} goals")
+            }
+            LevelErrorKind::NoBoxes => write!(f, "a sokoban level needs at least one box"),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+/// A level to start on that isn't in the [`LevelList`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoSuchLevel {
+    pub first: usize,
+    pub levels: usize,
+}
+
+impl fmt::Display for NoSuchLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.levels {
+            0 => write!(f, "no levels to start on"),
+            levels => write!(
+                f,
+                "no level {} to start on, the levels go from 0 to {}",
+                self.first,
+                levels - 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NoSuchLevel {}
+
+impl Level {
+    /// The maze the game shipped with.
+    pub fn classic() -> Level {
+        Level::parse(include_str!("../assets/levels/classic.txt"))
+            .expect("the bundled level is valid")
+    }
+
+    pub fn parse(source: &str) -> Result<Level, LevelError> {
+        let error = |line: usize, column: usize, kind| LevelError { line, column, kind };
+        let mut lines = source.lines().enumerate().map(|(i, l)| (i + 1, l));
+
+        let mut name = String::new();
+        let mut author = String::new();
+        let mut pellet_density = 0.75;
+        let mut frightened_seconds = DEFAULT_FRIGHTENED_SECONDS;
+        let mut wrap = Wrap::default();
+        let mut tunnel_slowdown = false;
+        let mut lives = DEFAULT_LIVES;
+        let mut extra_life_score = DEFAULT_EXTRA_LIFE_SCORE;
+        let mut rules = Rules::Maze;
+        let mut header_spawns = vec![];
+        let mut separator_line = None;
+        for (line_no, line) in lines.by_ref() {
+            if line.trim_end() == SEPARATOR {
+                separator_line = Some(line_no);
+                break;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                return Err(error(line_no, 1, LevelErrorKind::MalformedHeader));
+            };
+            let leading = value.chars().take_while(|c| c.is_whitespace()).count();
+            let value_column = key.chars().count() + 2 + leading;
+            let invalid = || {
+                error(
+                    line_no,
+                    value_column,
+                    LevelErrorKind::InvalidValue {
+                        key: key.trim().to_string(),
+                        value: value.trim().to_string(),
+                    },
+                )
+            };
+            let value = value.trim();
+            match key.trim() {
+                "name" => name = value.to_string(),
+                "author" => author = value.to_string(),
+                "pellet_density" => {
+                    pellet_density = value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|d| (0.0..=1.0).contains(d))
+                        .ok_or_else(invalid)?;
+                }
+                "frightened_time" => {
+                    frightened_seconds = value
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|s| *s >= 0)
+                        .ok_or_else(invalid)?;
+                }
+                "wrap" => {
+                    let (horizontal, vertical) = match value {
+                        "none" => (false, false),
+                        "horizontal" => (true, false),
+                        "vertical" => (false, true),
+                        "both" => (true, true),
+                        _ => return Err(invalid()),
+                    };
+                    wrap = Wrap {
+                        horizontal,
+                        vertical,
+                    };
+                }
+                "tunnel_slowdown" => {
+                    tunnel_slowdown = value.parse::<bool>().map_err(|_| invalid())?;
+                }
+                "lives" => {
+                    lives = value
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|n| *n >= 1)
+                        .ok_or_else(invalid)?;
+                }
+                "extra_life" => {
+                    extra_life_score = value
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|n| *n >= 0)
+                        .ok_or_else(invalid)?;
+                }
+                "rules" => {
+                    rules = match value {
+                        "maze" => Rules::Maze,
+                        "sokoban" => Rules::Sokoban,
+                        _ => return Err(invalid()),
+                    };
+                }
+                "ghost_spawn" => {
+                    let pos = parse_coord(value).ok_or_else(invalid)?;
+                    header_spawns.push((line_no, value_column, pos));
+                }
+                other => {
+                    return Err(error(
+                        line_no,
+                        1,
+                        LevelErrorKind::UnknownKey(other.to_string()),
+                    ))
+                }
+            }
+        }
+        let Some(separator_line) = separator_line else {
+            return Err(error(
+                source.lines().count() + 1,
+                1,
+                LevelErrorKind::MissingSeparator,
+            ));
+        };
+
+        let mut grid: Vec<Vec<char>> = vec![];
+        let mut player_spawn = None;
+        let mut ghost_spawns = vec![];
+        for (line_no, line) in lines {
+            let row: Vec<char> = line.chars().collect();
+            if row.is_empty() {
+                continue;
+            }
+            if let Some(first) = grid.first() {
+                if row.len() != first.len() {
+                    return Err(error(
+                        line_no,
+                        row.len().min(first.len()) + 1,
+                        LevelErrorKind::RaggedRow {
+                            expected: first.len(),
+                            found: row.len(),
+                        },
+                    ));
+                }
+            }
+            let y = grid.len() as i32;
+            for (x, &c) in row.iter().enumerate() {
+                if !is_level_glyph(c) {
+                    return Err(error(line_no, x + 1, LevelErrorKind::UnknownGlyph(c)));
+                }
+                if c == PLAYER || c == PLAYER_ON_GOAL {
+                    if player_spawn.is_some() {
+                        return Err(error(line_no, x + 1, LevelErrorKind::DuplicatePlayerSpawn));
+                    }
+                    player_spawn = Some((x as i32, y));
+                }
+                if c == GHOST {
+                    ghost_spawns.push((x as i32, y));
+                }
+            }
+            grid.push(row);
+        }
+        if grid.is_empty() {
+            return Err(error(separator_line + 1, 1, LevelErrorKind::EmptyMaze));
+        }
+        let Some(player_spawn) = player_spawn else {
+            return Err(error(
+                separator_line + 1,
+                1,
+                LevelErrorKind::MissingPlayerSpawn,
+            ));
+        };
+
+        if rules == Rules::Sokoban {
+            let cells = || grid.iter().flatten();
+            let boxes = cells().filter(|&&c| c == BOX || c == BOX_ON_GOAL).count();
+            let goals = cells()
+                .filter(|&&c| c == GOAL || c == BOX_ON_GOAL || c == PLAYER_ON_GOAL)
+                .count();
+            if boxes != goals {
+                return Err(error(
+                    separator_line + 1,
+                    1,
+                    LevelErrorKind::BoxGoalMismatch { boxes, goals },
+                ));
+            }
+            if boxes == 0 {
+                return Err(error(separator_line + 1, 1, LevelErrorKind::NoBoxes));
+            }
+        }
+
+        for (line_no, column, (x, y)) in header_spawns {
+            let open = grid
+                .get(y as usize)
+                .and_then(|row| row.get(x as usize))
+                .is_some_and(|c| !WALLS.contains(c));
+            if x < 0 || y < 0 || !open {
+                return Err(error(
+                    line_no,
+                    column,
+                    LevelErrorKind::SpawnOutOfBounds((x, y)),
+                ));
+            }
+            let cell = &mut grid[y as usize][x as usize];
+            if ![' ', PELLET, GHOST].contains(cell) {
+                return Err(error(
+                    line_no,
+                    column,
+                    LevelErrorKind::SpawnOccupied {
+                        spawn: (x, y),
+                        glyph: *cell,
+                    },
+                ));
+            }
+            *cell = GHOST;
+            if !ghost_spawns.contains(&(x, y)) {
+                ghost_spawns.push((x, y));
+            }
+        }
+
+        Ok(Level {
+            name,
+            author,
+            pellet_density,
+            frightened_seconds,
+            wrap,
+            tunnel_slowdown,
+            lives,
+            extra_life_score,
+            rules,
+            player_spawn,
+            ghost_spawns,
+            grid,
+        })
+    }
+
+    /// Builds a fresh [`Tilemap`], scattering pellets over the blank cells
+    /// with `rng`.
+    pub fn tilemap(&self, rng: &mut impl Rng) -> Tilemap {
+        Tilemap(
+            self.grid
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|&cell| match cell {
+                            BOX_ON_GOAL => vec![GOAL, BOX],
+                            PLAYER_ON_GOAL => vec![GOAL, PLAYER],
+                            ' ' if self.rules == Rules::Maze
+                                && rng.gen::<f32>() < self.pellet_density =>
+                            {
+                                vec![PELLET]
+                            }
+                            _ => vec![cell],
+                        })
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+}
+
+/// The levels a game plays in turn, starting over from the first once the
+/// last is cleared.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LevelList {
+    pub levels: Vec<Level>,
+    /// The level the game was started on, which a reset goes back to.
+    pub first: usize,
+    pub current: usize,
+}
+
+impl LevelList {
+    /// Starts on level `first` of `levels`, if there is one.
+    pub fn new(levels: Vec<Level>, first: usize) -> Result<Self, NoSuchLevel> {
+        if first >= levels.len() {
+            return Err(NoSuchLevel {
+                first,
+                levels: levels.len(),
+            });
+        }
+        Ok(LevelList {
+            levels,
+            first,
+            current: first,
+        })
+    }
+
+    pub fn current(&self) -> &Level {
+        &self.levels[self.current]
+    }
+
+    /// Moves on to the level after the current one.
+    pub fn advance(&mut self) -> &Level {
+        self.current = (self.current + 1) % self.levels.len();
+        self.current()
+    }
+
+    /// Goes back to the level the game was started on.
+    pub fn restart(&mut self) -> &Level {
+        self.current = self.first;
+        self.current()
+    }
+}
+
+fn is_level_glyph(c: char) -> bool {
+    WALLS.contains(&c)
+        || [
+            ' ',
+            PLAYER,
+            GHOST,
+            PELLET,
+            POWER_PELLET,
+            VOID,
+            BOX,
+            GOAL,
+            BOX_ON_GOAL,
+            PLAYER_ON_GOAL,
+        ]
+        .contains(&c)
+}
+
+fn parse_coord(value: &str) -> Option<(i32, i32)> {
+    let (x, y) = value.split_once(',')?;
+    Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(source: &str) -> LevelError {
+        Level::parse(source).expect_err("level should be rejected")
+    }
+
+    #[test]
+    fn parses_header_and_maze() {
+        let level = Level::parse(
+            "name: Corridor
+author: Someone
+lives: 5
+wrap: horizontal
+ghost_spawn: 3,1
+---
+#####
+#K  #
+#####",
+        )
+        .unwrap();
+        assert_eq!(level.name, "Corridor");
+        assert_eq!(level.author, "Someone");
+        assert_eq!(level.lives, 5);
+        assert!(level.wrap.horizontal && !level.wrap.vertical);
+        assert_eq!(level.player_spawn, (1, 1));
+        assert_eq!(level.ghost_spawns, [(3, 1)]);
+        assert_eq!(level.grid[1][3], GHOST);
+    }
+
+    #[test]
+    fn bundled_levels_parse() {
+        Level::classic();
+        Level::parse(include_str!("../assets/levels/sokoban.txt")).unwrap();
+    }
+
+    #[test]
+    fn missing_separator() {
+        let err = parse_error("name: x\n");
+        assert_eq!(err.kind, LevelErrorKind::MissingSeparator);
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn bad_header_lines() {
+        assert_eq!(
+            parse_error("nonsense\n---\n#K#\n").kind,
+            LevelErrorKind::MalformedHeader
+        );
+        assert_eq!(
+            parse_error("colour: red\n---\n#K#\n").kind,
+            LevelErrorKind::UnknownKey("colour".to_string())
+        );
+        let err = parse_error("lives: 0\n---\n#K#\n");
+        assert_eq!(
+            err.kind,
+            LevelErrorKind::InvalidValue {
+                key: "lives".to_string(),
+                value: "0".to_string(),
+            }
+        );
+        assert_eq!((err.line, err.column), (1, 8));
+    }
+
+    #[test]
+    fn bad_maze_rows() {
+        assert_eq!(parse_error("---\n").kind, LevelErrorKind::EmptyMaze);
+        let err = parse_error("---\n#K?#\n");
+        assert_eq!(err.kind, LevelErrorKind::UnknownGlyph('?'));
+        assert_eq!((err.line, err.column), (2, 3));
+        assert_eq!(
+            parse_error("---\n#K#\n##\n").kind,
+            LevelErrorKind::RaggedRow {
+                expected: 3,
+                found: 2
+            }
+        );
+    }
+
+    #[test]
+    fn player_spawns() {
+        assert_eq!(
+            parse_error("---\n# #\n").kind,
+            LevelErrorKind::MissingPlayerSpawn
+        );
+        let err = parse_error("---\n#KK#\n");
+        assert_eq!(err.kind, LevelErrorKind::DuplicatePlayerSpawn);
+        assert_eq!((err.line, err.column), (2, 3));
+    }
+
+    #[test]
+    fn ghost_spawn_outside_the_maze_or_in_a_wall() {
+        assert_eq!(
+            parse_error("ghost_spawn: 9,9\n---\n#####\n#K  #\n#####\n").kind,
+            LevelErrorKind::SpawnOutOfBounds((9, 9))
+        );
+        assert_eq!(
+            parse_error("ghost_spawn: 0,1\n---\n#####\n#K  #\n#####\n").kind,
+            LevelErrorKind::SpawnOutOfBounds((0, 1))
+        );
+    }
+
+    #[test]
+    fn ghost_spawn_on_the_player_or_a_box() {
+        let err = parse_error("ghost_spawn: 2,1\n---\n#####\n# K #\n#####\n");
+        assert_eq!(
+            err.kind,
+            LevelErrorKind::SpawnOccupied {
+                spawn: (2, 1),
+                glyph: PLAYER
+            }
+        );
+        assert_eq!((err.line, err.column), (1, 14));
+        assert_eq!(
+            parse_error("rules: sokoban\nghost_spawn: 1,1\n---\n#####\n#$.K#\n#####\n").kind,
+            LevelErrorKind::SpawnOccupied {
+                spawn: (1, 1),
+                glyph: BOX
+            }
+        );
+    }
+
+    #[test]
+    fn sokoban_boxes_and_goals() {
+        assert_eq!(
+            parse_error("rules: sokoban\n---\n######\n#K$$.#\n######\n").kind,
+            LevelErrorKind::BoxGoalMismatch { boxes: 2, goals: 1 }
+        );
+        assert_eq!(
+            parse_error("rules: sokoban\n---\n####\n#K #\n####\n").kind,
+            LevelErrorKind::NoBoxes
+        );
+        // A box already on a goal and the player on a goal both count.
+        let level = Level::parse("rules: sokoban\n---\n######\n#+*$ #\n######\n").unwrap();
+        assert_eq!(level.player_spawn, (1, 1));
+    }
+
+    #[test]
+    fn level_list_starts_on_a_level_it_has() {
+        let levels = vec![Level::classic(), Level::classic()];
+        let mut list = LevelList::new(levels.clone(), 1).unwrap();
+        assert_eq!(list.current, 1);
+        list.advance();
+        assert_eq!(list.current, 0);
+        list.restart();
+        assert_eq!(list.current, 1);
+        assert_eq!(
+            LevelList::new(levels, 2),
+            Err(NoSuchLevel {
+                first: 2,
+                levels: 2
+            })
+        );
+    }
+}
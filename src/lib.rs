@@ -0,0 +1,19 @@
+//! Headless core of the game. The Bevy frontend lives in `main.rs`; anything
+//! in this crate can be driven without a window.
+
+pub mod bindings;
+pub mod checksum;
+pub mod ghost;
+pub mod highscores;
+pub mod history;
+pub mod level;
+pub mod replay;
+pub mod rng;
+#[cfg(feature = "serde")]
+pub mod save;
+pub mod simulation;
+pub mod sokoban;
+pub mod speed;
+pub mod swipe;
+pub mod tilemap;
+pub mod xsb;
@@ -1,295 +1,809 @@
-use bevy::time::common_conditions::on_timer;
-use bevy::utils::HashSet;
-use rand::{self};
+use std::io::Write;
 use std::time::Duration;
 
+use bevy::reflect::{DynamicEnum, DynamicVariant, FromReflect, TypeInfo, Typed, VariantInfo};
 use bevy::sprite::{Wireframe2dConfig, Wireframe2dPlugin};
+use bevy::utils::HashSet;
 use bevy::{
     prelude::*,
     sprite::{MaterialMesh2dBundle, Mesh2dHandle},
 };
+use bevy_egui::{egui, EguiContexts, EguiPlugin};
 
-use ratatui::{
-    layout::{Constraint, Layout},
-    prelude::{Stylize, Terminal},
-    widgets::{Block, Borders, Paragraph, Wrap},
-};
+use mystuff::bindings::{Bindings, COMMANDS};
+use mystuff::checksum::{self, Checksum};
+use mystuff::ghost::{GhostState, Personality, TICKS_PER_SECOND};
+use mystuff::highscores::{HighScore, HighScores, ScoreStorage};
+use mystuff::level::{Level, LevelError, LevelList};
+use mystuff::replay::{self, command_name, Replay};
+use mystuff::rng::GameRng;
+use mystuff::simulation::{Command, GameEvent, MoveDirection, Rules, Simulation};
+use mystuff::speed::FULL_SPEED;
+use mystuff::swipe::{Swipes, Touch, TouchPhase};
+use mystuff::tilemap::{BOX, GOAL, PELLET, PLAYER, POWER_PELLET, WALLS};
+use mystuff::xsb::LevelPack;
 
-#[derive(States, Clone, PartialEq, Debug, Hash, Eq, Copy)]
-enum MoveDirection {
-    Up,
-    Down,
-    Left,
-    Right,
+#[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
+enum AppState {
+    MainMenu,
+    Playing,
+    Paused,
+    /// The player was caught and the death animation is playing.
+    Dying,
+    LevelComplete,
+    /// The game was reset or moved on to the next level, and every entity is
+    /// being rebuilt for it.
+    Restarting,
+    GameOver,
 }
 
-#[derive(Clone, PartialEq, Debug, Hash, Eq, Copy)]
-struct Scores {
-    high_score: i32,
-    current_score: i32,
+/// The running [`Simulation`], shared with the systems that render it.
+#[derive(Resource, Deref, DerefMut)]
+struct Game(Simulation);
+
+/// Which screen of the main menu is showing, and the level picked to start on.
+#[derive(Resource, Default)]
+struct Menu {
+    page: MenuPage,
+    level: usize,
+    /// Whether the menu was opened from a paused game, which Continue goes
+    /// back to.
+    paused_game: bool,
+    /// The command waiting for an input to bind on the controls screen.
+    capture: Option<Capture>,
 }
 
-#[derive(Clone, PartialEq, Debug, Hash, Eq, Copy)]
-enum Command {
-    Quit,
-    Move(MoveDirection),
-    Reset,
+struct Capture {
+    command: Command,
+    /// Keys already down when the capture started, whose release shouldn't
+    /// be taken for the new binding.
+    held: Vec<KeyCode>,
+    /// Why the last input pressed wasn't bound.
+    refused: Option<String>,
 }
 
-#[derive(States, Debug, Clone, PartialEq, Eq, Hash)]
-enum AppState {
-    Playing,
-    Restarting,
-    GameOver,
+#[derive(Default, PartialEq, Eq)]
+enum MenuPage {
+    #[default]
+    Main,
+    LevelSelect,
+    HighScores,
+    Settings,
+    Controls,
+}
+
+/// Choices from the settings screen, used by the next game started.
+#[derive(Resource)]
+struct Settings {
+    lives: i32,
 }
 
-#[derive(Resource, Debug, Hash, Clone, PartialEq, Eq)]
-struct GameState {
-    current_direction: Option<MoveDirection>,
-    player_position: (i32, i32),
-    scores: Scores,
-    ghost_1_pos: (i32, i32),
-    ghost_1_cur_dir: Option<MoveDirection>,
-    num_moves_ghost_1_in_dir: i32,
+/// How long the cleared maze stays up before the next level starts.
+#[derive(Resource, Deref, DerefMut)]
+struct LevelCompleteTimer(Timer);
+
+/// Asks for every entity to be rebuilt from the [`Game`], after the map was
+/// swapped out for a different one.
+#[derive(Event)]
+struct RespawnLevel;
+
+/// The high score table and where it is kept. `recorded` is set once the
+/// game being played has been put on the table, so it only goes on once
+/// however it ends.
+#[derive(Resource)]
+struct HighScoreTable {
+    scores: HighScores,
+    storage: Box<dyn ScoreStorage + Send + Sync>,
+    recorded: bool,
 }
 
-fn read_input(keys: Res<ButtonInput<KeyCode>>) -> Option<Command> {
-    if keys.just_released(KeyCode::KeyQ) {
-        return Some(Command::Quit);
-    }
-    if keys.just_released(KeyCode::KeyW) {
-        return Some(Command::Move(MoveDirection::Up));
+/// Simulation steps taken so far, which replays time their inputs by.
+#[derive(Resource, Default)]
+struct Ticks(u32);
+
+/// The replay file commands and checksums are being recorded to. Lines are
+/// written as they happen, so the replay survives the game crashing.
+/// `replay` holds the header, without any inputs, and `last` the checksum
+/// after the last tick, to tell which fields changed since.
+#[derive(Resource)]
+struct Recording {
+    file: std::fs::File,
+    path: String,
+    replay: Replay,
+    last: Option<Checksum>,
+}
+
+impl Recording {
+    /// Starts the replay file at `path` with the header of `replay`.
+    fn create(path: String, replay: Replay) -> std::io::Result<Recording> {
+        let mut recording = Recording {
+            file: std::fs::File::create(&path)?,
+            path,
+            replay,
+            last: None,
+        };
+        recording.append(&recording.replay.header());
+        Ok(recording)
     }
-    if keys.just_released(KeyCode::KeyA) {
-        return Some(Command::Move(MoveDirection::Left));
+
+    /// Starts the replay file over for a new game on level `first` of the
+    /// list with `lives` lives, so it only ever holds the latest game.
+    fn restart(&mut self, first: usize, lives: i32) {
+        self.replay.first = first;
+        self.replay.lives = Some(lives);
+        self.last = None;
+        match std::fs::File::create(&self.path) {
+            Ok(file) => self.file = file,
+            Err(err) => warn!("Couldn't restart replay {}: {err}", self.path),
+        }
+        let header = self.replay.header();
+        self.append(&header);
     }
-    if keys.just_released(KeyCode::KeyS) {
-        return Some(Command::Move(MoveDirection::Down));
+
+    fn record(&mut self, tick: u32, cmd: Command) {
+        self.append(&replay::input_line(tick, cmd));
     }
-    if keys.just_released(KeyCode::KeyD) {
-        return Some(Command::Move(MoveDirection::Right));
+
+    /// Records the checksum of `sim` after `tick` steps, along with every
+    /// field that changed since the last one.
+    fn record_checksum(&mut self, tick: u32, sim: &Simulation) {
+        let checksum = Checksum::of(sim);
+        for (field, value) in checksum::values(sim).iter().enumerate() {
+            if self
+                .last
+                .is_some_and(|last| last.0[field] == checksum.0[field])
+            {
+                continue;
+            }
+            self.append(&replay::value_line(tick, field, value));
+        }
+        self.append(&replay::checksum_line(tick, &checksum));
+        self.last = Some(checksum);
     }
-    if keys.just_released(KeyCode::KeyR) {
-        return Some(Command::Reset);
+
+    fn append(&mut self, line: &str) {
+        if let Err(err) = self.file.write_all(line.as_bytes()) {
+            warn!("Couldn't write replay to {}: {err}", self.path);
+        }
     }
-    return None;
 }
 
-#[derive(Debug)]
-struct Update {
-    coord: (i32, i32),
-    value: Vec<(Transform, Entity)>,
+/// A replay fed in place of the keyboard. `next` is the first input not yet
+/// played, and `diverged` is set once the game has stopped matching the
+/// recorded checksums.
+#[derive(Resource)]
+struct Playback {
+    replay: Replay,
+    next: usize,
+    diverged: bool,
 }
 
-#[derive(Resource, Debug)]
-struct Updates(pub Vec<Update>);
+impl Playback {
+    /// The inputs due before tick `tick` is stepped.
+    fn take(&mut self, tick: u32) -> Vec<Command> {
+        let due: Vec<Command> = self.replay.inputs[self.next..]
+            .iter()
+            .take_while(|(at, _)| *at <= tick)
+            .map(|(_, cmd)| *cmd)
+            .collect();
+        self.next += due.len();
+        due
+    }
 
-fn update_resourcemap(mut resource_map: ResMut<ResourceMap>, mut updates: ResMut<Updates>) {
-    updates.0.drain(..).for_each(|update| {
-        resource_map.0[update.coord.1 as usize][update.coord.0 as usize] = update.value;
-    });
+    /// Whether a reset is among the inputs due before tick `tick`.
+    fn reset_due(&self, tick: u32) -> bool {
+        self.replay.inputs[self.next..]
+            .iter()
+            .take_while(|(at, _)| *at <= tick)
+            .any(|(_, cmd)| *cmd == Command::Reset)
+    }
 }
 
-fn player_move(
-    mut game_state: ResMut<GameState>,
-    next_pos: ResMut<NextPacmanPosition>,
-    tilemap: Res<Tilemap>,
+/// Goes back to playing from the game over screen once the replay gets to
+/// where the game was played again, since ticks and so playback stop there.
+fn resume_playback(
+    playback: Res<Playback>,
+    ticks: Res<Ticks>,
     mut next_state: ResMut<NextState<AppState>>,
-    resourcemap: Res<ResourceMap>,
-    mut commands: Commands,
-    window_query: Query<&Window>,
-    mut updates: ResMut<Updates>,
 ) {
-    let player_entity = &resourcemap.0[game_state.player_position.1 as usize]
-        [game_state.player_position.0 as usize];
-    if next_pos.pos.x as i32 == game_state.player_position.0
-        && next_pos.pos.y as i32 == game_state.player_position.1
-    {
-        return;
-    }
-    if player_entity.len() == 0 {
-        panic!("The player didnt exist?!");
+    if playback.reset_due(ticks.0) {
+        next_state.set(AppState::Playing);
     }
+}
 
-    if next_pos.contents.contains(&'m') {
-        next_state.set(AppState::GameOver);
+/// After every tick, records the simulation's checksum or, when playing a
+/// replay back, reports the first tick where it differs from the recorded
+/// one.
+fn checksum_tick(
+    game: Res<Game>,
+    ticks: Res<Ticks>,
+    recording: Option<ResMut<Recording>>,
+    playback: Option<ResMut<Playback>>,
+) {
+    if let Some(mut recording) = recording {
+        recording.record_checksum(ticks.0, &game);
+    }
+    let Some(mut playback) = playback else {
         return;
+    };
+    if playback.diverged {
+        return;
+    }
+    if let Err(divergence) = playback.replay.check(ticks.0, &game) {
+        error!("Replay {divergence}");
+        playback.diverged = true;
     }
+}
 
-    if vec!['#', '_', '-', '|']
-        .iter()
-        .collect::<HashSet<&char>>()
-        .intersection(&next_pos.contents.iter().collect::<HashSet<&char>>())
-        .collect::<Vec<&&char>>()
-        .len()
-        > 0
-    {
-        next_state.set(AppState::Playing);
-        return;
+/// Feeds the replay's inputs in before each tick, as [`Replay::verify`] does.
+/// This runs with the fixed-timestep ticks rather than once a frame so
+/// several ticks in one frame each get their own inputs. Resets go to the
+/// simulation with the rest, landing on exactly the tick they were recorded
+/// on.
+fn play_back(
+    mut playback: ResMut<Playback>,
+    ticks: Res<Ticks>,
+    mut pending: ResMut<PendingCommands>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    for cmd in playback.take(ticks.0) {
+        match cmd {
+            Command::Quit => next_state.set(AppState::GameOver),
+            _ => pending.0.push(cmd),
+        }
     }
+}
 
-    if next_pos.contents.contains(&'•') {
-        game_state.scores.current_score += 1;
-        resourcemap.0[next_pos.pos.y as usize][next_pos.pos.x as usize]
-            .iter()
-            .for_each(|t: &(Transform, Entity)| {
-                commands.entity(t.1).despawn();
-            });
+/// Commands read since the last tick, handed to [`Simulation::step`].
+#[derive(Resource, Default)]
+struct PendingCommands(Vec<Command>);
+
+/// What each key and gamepad input does, kept in [`BINDINGS_PATH`].
+#[derive(Resource, Deref, DerefMut)]
+struct InputBindings(Bindings);
+
+const BINDINGS_PATH: &str = "bindings.cfg";
+
+/// A key, gamepad button or stick direction that can be bound to a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum InputSource {
+    Key(KeyCode),
+    Button(GamepadButtonType),
+    /// A stick pushed more than [`STICK_THRESHOLD`] along an axis, in the
+    /// positive direction if the flag is set.
+    Stick(GamepadAxisType, bool),
+}
+
+/// How far a stick has to be pushed to count as a press.
+const STICK_THRESHOLD: f32 = 0.5;
+
+const STICKS: [(&str, GamepadAxisType, bool); 8] = [
+    ("LeftStickUp", GamepadAxisType::LeftStickY, true),
+    ("LeftStickDown", GamepadAxisType::LeftStickY, false),
+    ("LeftStickLeft", GamepadAxisType::LeftStickX, false),
+    ("LeftStickRight", GamepadAxisType::LeftStickX, true),
+    ("RightStickUp", GamepadAxisType::RightStickY, true),
+    ("RightStickDown", GamepadAxisType::RightStickY, false),
+    ("RightStickLeft", GamepadAxisType::RightStickX, false),
+    ("RightStickRight", GamepadAxisType::RightStickX, true),
+];
+
+impl InputSource {
+    /// Reads the name a [`Bindings`] file uses: the [`KeyCode`] for a key,
+    /// `Pad` and the [`GamepadButtonType`] for a button, and one of
+    /// [`STICKS`] for a stick.
+    fn parse(name: &str) -> Option<InputSource> {
+        if let Some((_, axis, positive)) = STICKS.iter().find(|(stick, ..)| *stick == name) {
+            return Some(InputSource::Stick(*axis, *positive));
+        }
+        if let Some(button) = name.strip_prefix("Pad") {
+            return unit_variant(button).map(InputSource::Button);
+        }
+        unit_variant(name).map(InputSource::Key)
     }
 
-    updates.0.push(Update {
-        coord: game_state.player_position,
-        value: vec![],
-    });
+    fn name(&self) -> String {
+        match self {
+            InputSource::Key(key) => format!("{key:?}"),
+            InputSource::Button(button) => format!("Pad{button:?}"),
+            InputSource::Stick(axis, positive) => STICKS
+                .iter()
+                .find(|(_, a, p)| a == axis && p == positive)
+                .map_or_else(|| format!("{axis:?}"), |(name, ..)| name.to_string()),
+        }
+    }
+}
 
-    // create the new transform and entity
-    let window = window_query.single();
-    let border_len = (window.height()) / (tilemap.0.len() as f32);
-    let top_left = Vec3::new(-window.width() / 2.0, window.height() / 2.0, 0.0);
-    let x = next_pos.pos.x as f32 * border_len + (border_len / 2.0);
-    let y = -(next_pos.pos.y * border_len) - (border_len / 2.0);
-    let transform = Transform {
-        translation: top_left + Vec3::new(x, y, 0.0),
-        rotation: calc_rotation(game_state.current_direction.unwrap()),
-        ..default()
+/// The variant of `T` called `name`, if it has one that holds no data.
+fn unit_variant<T: FromReflect + Typed>(name: &str) -> Option<T> {
+    let TypeInfo::Enum(info) = T::type_info() else {
+        return None;
+    };
+    // `from_reflect` panics on a variant the enum doesn't have.
+    let Some(VariantInfo::Unit(_)) = info.variant(name) else {
+        return None;
     };
+    T::from_reflect(&DynamicEnum::new(name, DynamicVariant::Unit))
+}
 
-    commands.entity(player_entity[0].1).insert(transform);
-    updates.0.push(Update {
-        coord: (next_pos.pos.x as i32, next_pos.pos.y as i32),
-        value: vec![(transform, player_entity[0].1)],
-    });
+/// An input that went off this frame: a key or button let go, or a stick
+/// pushed past [`STICK_THRESHOLD`].
+#[derive(Event)]
+struct InputPressed(InputSource);
 
-    game_state.player_position = (next_pos.pos.x as i32, next_pos.pos.y as i32);
-    next_state.set(AppState::Playing);
+fn read_inputs(
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepads: Res<Gamepads>,
+    mut pushed: Local<HashSet<(Gamepad, InputSource)>>,
+    mut pressed: EventWriter<InputPressed>,
+) {
+    for key in keys.get_just_released() {
+        pressed.send(InputPressed(InputSource::Key(*key)));
+    }
+    for button in buttons.get_just_released() {
+        pressed.send(InputPressed(InputSource::Button(button.button_type)));
+    }
+    for gamepad in gamepads.iter() {
+        for (_, axis, positive) in STICKS {
+            let value = axes.get(GamepadAxis::new(gamepad, axis)).unwrap_or(0.0);
+            let source = InputSource::Stick(axis, positive);
+            let past = if positive {
+                value > STICK_THRESHOLD
+            } else {
+                value < -STICK_THRESHOLD
+            };
+            if !past {
+                pushed.remove(&(gamepad, source));
+            } else if pushed.insert((gamepad, source)) {
+                pressed.send(InputPressed(source));
+            }
+        }
+    }
 }
 
-fn ghost1_move(
-    mut game_state: ResMut<GameState>,
-    next_pos: ResMut<NextGhost1Position>,
-    tilemap: Res<Tilemap>,
-    mut next_state: ResMut<NextState<AppState>>,
-    resourcemap: Res<ResourceMap>,
-    mut commands: Commands,
-    window_query: Query<&Window>,
-    mut updates: ResMut<Updates>,
+/// Swipes across the screen, and whether it has been touched at all, which
+/// brings up the d-pad.
+#[derive(Resource, Default)]
+struct TouchControls {
+    swipes: Swipes,
+    seen: bool,
+}
+
+/// A move from a swipe or the d-pad.
+#[derive(Event)]
+struct TouchMove(MoveDirection);
+
+/// Turns swipes across the screen into moves. Touches that start on the
+/// d-pad or a menu are left to egui.
+fn read_swipes(
+    mut touches: EventReader<TouchInput>,
+    mut contexts: EguiContexts,
+    time: Res<Time<Real>>,
+    mut controls: ResMut<TouchControls>,
+    mut moves: EventWriter<TouchMove>,
 ) {
-    let ghost_entity =
-        &resourcemap.0[game_state.ghost_1_pos.1 as usize][game_state.ghost_1_pos.0 as usize];
-    if next_pos.pos.x as i32 == game_state.ghost_1_pos.0
-        && next_pos.pos.y as i32 == game_state.ghost_1_pos.1
-    {
-        return;
-    }
-    if ghost_entity.len() == 0 {
-        panic!("The ghost didnt exist?!");
+    for input in touches.read() {
+        controls.seen = true;
+        let phase = match input.phase {
+            bevy::input::touch::TouchPhase::Started => TouchPhase::Started,
+            bevy::input::touch::TouchPhase::Moved => TouchPhase::Moved,
+            bevy::input::touch::TouchPhase::Ended => TouchPhase::Ended,
+            bevy::input::touch::TouchPhase::Canceled => TouchPhase::Canceled,
+        };
+        if phase == TouchPhase::Started && contexts.ctx_mut().is_pointer_over_area() {
+            continue;
+        }
+        let touch = Touch {
+            id: input.id,
+            phase,
+            position: (input.position.x, input.position.y),
+            time: time.elapsed_seconds_f64(),
+        };
+        if let Some(direction) = controls.swipes.touch(&touch) {
+            moves.send(TouchMove(direction));
+        }
     }
+}
 
-    if next_pos.contents.contains(&'K') {
-        next_state.set(AppState::GameOver);
+/// Arrow buttons in the bottom right corner, for when there's no keyboard.
+/// They only show up once the screen has been touched.
+fn dpad(
+    mut contexts: EguiContexts,
+    controls: Res<TouchControls>,
+    mut moves: EventWriter<TouchMove>,
+) {
+    if !controls.seen {
         return;
     }
-
-    if vec!['#', '_', '-', '|']
-        .iter()
-        .collect::<HashSet<&char>>()
-        .intersection(&next_pos.contents.iter().collect::<HashSet<&char>>())
-        .collect::<Vec<&&char>>()
-        .len()
-        > 0
-    {}
-
-    if next_pos.contents.contains(&'•') {
-        game_state.scores.current_score += 1;
-        resourcemap.0[next_pos.pos.y as usize][next_pos.pos.x as usize]
-            .iter()
-            .for_each(|t: &(Transform, Entity)| {
-                commands.entity(t.1).despawn();
+    egui::Area::new(egui::Id::new("dpad"))
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-12.0, -12.0])
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("dpad_grid").show(ui, |ui| {
+                let mut button = |ui: &mut egui::Ui, text: &str, direction| {
+                    let button = egui::Button::new(egui::RichText::new(text).size(28.0));
+                    if ui.add_sized([56.0, 56.0], button).clicked() {
+                        moves.send(TouchMove(direction));
+                    }
+                };
+                ui.label("");
+                button(ui, "⬆", MoveDirection::Up);
+                ui.end_row();
+                button(ui, "⬅", MoveDirection::Left);
+                ui.label("");
+                button(ui, "➡", MoveDirection::Right);
+                ui.end_row();
+                ui.label("");
+                button(ui, "⬇", MoveDirection::Down);
+                ui.end_row();
             });
+        });
+}
+
+/// Reads the bindings from [`BINDINGS_PATH`], falling back on the defaults
+/// if there is no such file or it can't be read.
+fn load_bindings() -> Bindings {
+    let bindings = match std::fs::read_to_string(BINDINGS_PATH) {
+        Ok(source) => Bindings::parse(&source).unwrap_or_else(|err| {
+            eprintln!("{BINDINGS_PATH}: {err}");
+            Bindings::default()
+        }),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Bindings::default(),
+        Err(err) => {
+            eprintln!("{BINDINGS_PATH}: {err}");
+            Bindings::default()
+        }
+    };
+    for command in COMMANDS {
+        for input in bindings.inputs(command) {
+            if InputSource::parse(input).is_none() {
+                eprintln!("{BINDINGS_PATH}: unknown input `{input}`");
+            }
+        }
+    }
+    for conflict in bindings.conflicts() {
+        eprintln!("{BINDINGS_PATH}: {conflict}");
     }
+    bindings
+}
 
-    updates.0.push(Update {
-        coord: game_state.player_position,
-        value: vec![],
-    });
+/// How often the simulation steps, at normal game speed.
+const TICK: Duration = Duration::from_millis(1000 / TICKS_PER_SECOND as u64);
 
-    // create the new transform and entity
-    let window = window_query.single();
-    let border_len = (window.height()) / (tilemap.0.len() as f32);
-    let top_left = Vec3::new(-window.width() / 2.0, window.height() / 2.0, 0.0);
-    let x = next_pos.pos.x as f32 * border_len + (border_len / 2.0);
-    let y = -(next_pos.pos.y * border_len) - (border_len / 2.0);
-    let transform = Transform {
-        translation: top_left + Vec3::new(x, y, 0.0),
-        rotation: calc_rotation(game_state.current_direction.unwrap()),
-        ..default()
-    };
+/// How long something moving at `speed` takes to cross a tile.
+fn glide_time(speed: i32) -> f32 {
+    TICK.as_secs_f32() * FULL_SPEED as f32 / speed.max(1) as f32
+}
 
-    commands.entity(ghost_entity[0].1).insert(transform);
-    updates.0.push(Update {
-        coord: (next_pos.pos.x as i32, next_pos.pos.y as i32),
-        value: vec![(transform, ghost_entity[0].1)],
-    });
+/// Where an entity is gliding from and to. The simulation only knows which
+/// tile things are on, so moving entities are drawn part of the way between
+/// their last tile and their new one, going by how long ago they set off and
+/// how long their speed takes to cross a tile. Times are in seconds of
+/// [`Virtual`] time, so gliding keeps pace with the game speed.
+#[derive(Component)]
+struct Motion {
+    from: Transform,
+    to: Transform,
+    started: f32,
+    duration: f32,
+}
 
-    game_state.player_position = (next_pos.pos.x as i32, next_pos.pos.y as i32);
-    next_state.set(AppState::Playing);
+impl Motion {
+    fn at_rest(transform: Transform) -> Self {
+        Motion {
+            from: transform,
+            to: transform,
+            started: 0.0,
+            duration: 0.0,
+        }
+    }
+
+    /// Heads for `to` from `current`, wherever the entity is drawn now,
+    /// taking `duration` seconds from `now`. Moves further than
+    /// `max_distance`, like going through a tunnel or back to the spawn, jump
+    /// straight there instead.
+    fn retarget(
+        &mut self,
+        current: &Transform,
+        to: Transform,
+        max_distance: f32,
+        now: f32,
+        duration: f32,
+    ) {
+        let jump = current.translation.distance(to.translation) > max_distance;
+        self.from = if jump { to } else { *current };
+        self.to = to;
+        self.started = now;
+        self.duration = duration;
+    }
+}
+
+/// Draws every [`Motion`] where it has got to this frame. Turning eases out
+/// over the first quarter of the move.
+fn interpolate(time: Res<Time<Virtual>>, mut movers: Query<(&Motion, &mut Transform)>) {
+    let now = time.elapsed_seconds();
+    for (motion, mut transform) in &mut movers {
+        let progress = if motion.duration > 0.0 {
+            ((now - motion.started) / motion.duration).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let turn = (progress * 4.0).min(1.0);
+        let turn = turn * (2.0 - turn);
+        transform.translation = motion
+            .from
+            .translation
+            .lerp(motion.to.translation, progress);
+        transform.rotation = motion.from.rotation.slerp(motion.to.rotation, turn);
+    }
+}
+
+/// Starts the player's entity gliding to `to`, or just puts it there if it
+/// was spawned this tick and has nothing to glide from yet.
+fn glide(
+    commands: &mut Commands,
+    movers: &mut Query<(&Transform, &mut Motion)>,
+    entity: Entity,
+    to: Transform,
+    max_distance: f32,
+    (now, duration): (f32, f32),
+) {
+    match movers.get_mut(entity) {
+        Ok((current, mut motion)) => motion.retarget(current, to, max_distance, now, duration),
+        Err(_) => {
+            commands.entity(entity).insert((to, Motion::at_rest(to)));
+        }
+    }
 }
 
-#[rustfmt::skip]
-fn create_tilemap() -> Tilemap {
-    Tilemap(vec![
-        vec![vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_'], vec!['_']],
-        vec![vec!['|'], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec!['|']],
-        vec![vec!['|'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['|']],
-        vec![vec!['|'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['|']],
-        vec![vec!['|'], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec!['|']],
-        vec![vec!['|'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['|']],
-        vec![vec!['|'], vec![' '], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec![' '], vec!['|']],
-        vec![vec!['|'], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec!['|']],
-        vec![vec!['|'], vec!['Z'], vec!['Z'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['Z'], vec!['Z'], vec!['|']],
-        vec![vec!['|'], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec!['|']],
-        vec![vec![' '], vec![' '], vec!['K'], vec![' '], vec![' '], vec![' '], vec![' '], vec!['#'], vec!['#'], vec!['m'], vec!['#'], vec!['#'], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' ']],
-        vec![vec!['|'], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec!['|']],
-        vec![vec!['|'], vec![' '], vec![' '], vec!['#'], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec!['#'], vec![' '], vec![' '], vec!['|']],
-        vec![vec!['|'], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec!['|']],
-        vec![vec!['|'], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec!['|']],
-        vec![vec!['|'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec![' '], vec!['|']],
-        vec![vec!['|'], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec!['|']],
-        vec![vec!['|'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec![' '], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec![' '], vec!['#'], vec![' '], vec!['#'], vec![' '], vec![' '], vec!['|']],
-        vec![vec!['|'], vec![' '], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec!['#'], vec![' '], vec![' '], vec![' '], vec![' '], vec!['|']],
-        vec![vec!['|'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['#'], vec![' '], vec!['#'], vec!['#'], vec!['#'], vec!['#'], vec!['#'], vec!['#'], vec![' '], vec!['|']],
-        vec![vec!['|'], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec![' '], vec!['|']],
-        vec![vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-'], vec!['-']],
-    ]
-    .into_iter()
-    .map(|row| {
-        row.into_iter().map(|cell| {  
-            if cell[0] != ' ' { 
-                return cell;
+/// Centre of the tile at `pos`, in world coordinates.
+fn tile_translation(window: &Window, rows: usize, pos: (i32, i32), z: f32) -> Vec3 {
+    let border_len = window.height() / rows as f32;
+    let top_left = Vec3::new(-window.width() / 2.0, window.height() / 2.0, 0.0);
+    let x = pos.0 as f32 * border_len + (border_len / 2.0);
+    let y = -(pos.1 as f32 * border_len) - (border_len / 2.0);
+    top_left + Vec3::new(x, y, z)
+}
+
+/// Advances the simulation by one tick and mirrors the resulting events onto
+/// the entities in the [`ResourceMap`].
+#[allow(clippy::too_many_arguments)]
+fn tick(
+    mut game: ResMut<Game>,
+    mut ticks: ResMut<Ticks>,
+    mut pending: ResMut<PendingCommands>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut resourcemap: ResMut<ResourceMap>,
+    mut commands: Commands,
+    window_query: Query<&Window>,
+    assets: Res<TileAssets>,
+    time: Res<Time<Virtual>>,
+    mut movers: Query<(&Transform, &mut Motion)>,
+    mut table: ResMut<HighScoreTable>,
+) {
+    let inputs = std::mem::take(&mut pending.0);
+    let events = game.step(&inputs);
+    ticks.0 += 1;
+    let window = window_query.single();
+    let rows = game.tilemap.height();
+    let tile = window.height() / rows as f32;
+    let player_glide = (time.elapsed_seconds(), glide_time(game.player_speed()));
+    // The player stays where they were caught for the death animation, and
+    // everything is respawned once it has played.
+    let mut caught = false;
+
+    for event in events {
+        match event {
+            GameEvent::PelletEaten { pos } | GameEvent::PowerPelletEaten { pos } => {
+                resourcemap.0[pos.1 as usize][pos.0 as usize]
+                    .drain(..)
+                    .for_each(|(_, entity)| commands.entity(entity).despawn());
             }
-            let num = rand::random::<f32>();
-            if num < 0.75 { 
-                return vec!['•'];
+            GameEvent::PelletRestored { pos, glyph } => {
+                let pellet = spawn_glyph(&mut commands, &assets, window, rows, pos, glyph);
+                resourcemap.0[pos.1 as usize][pos.0 as usize].extend(pellet);
             }
-            return cell;
-        })
-        .collect::<Vec<Vec<char>>>()
-    })
-    .collect::<Vec<Vec<Vec<char>>>>())
+            GameEvent::PlayerMoved {
+                from,
+                to,
+                direction,
+            } => {
+                let player_entity = resourcemap.0[from.1 as usize][from.0 as usize]
+                    .pop()
+                    .expect("The player didnt exist?!");
+                let transform = Transform {
+                    translation: tile_translation(window, rows, to, 0.0),
+                    rotation: calc_rotation(direction),
+                    ..default()
+                };
+                glide(
+                    &mut commands,
+                    &mut movers,
+                    player_entity.1,
+                    transform,
+                    tile,
+                    player_glide,
+                );
+                resourcemap.0[to.1 as usize][to.0 as usize].push((transform, player_entity.1));
+            }
+            GameEvent::BoxPushed { from, to } => {
+                let box_entity = resourcemap.0[from.1 as usize][from.0 as usize]
+                    .pop()
+                    .expect("The box didnt exist?!");
+                let transform =
+                    Transform::from_translation(tile_translation(window, rows, to, 0.0));
+                commands.entity(box_entity.1).insert(transform);
+                resourcemap.0[to.1 as usize][to.0 as usize].push((transform, box_entity.1));
+            }
+            GameEvent::LevelComplete => {
+                info!(
+                    "Level complete in {} moves and {} pushes",
+                    game.state.moves, game.state.pushes
+                );
+                next_state.set(AppState::LevelComplete);
+            }
+            GameEvent::GhostEaten { points, .. } => info!("Ate a ghost for {points} points"),
+            GameEvent::LifeLost { .. } => {
+                caught = true;
+                next_state.set(AppState::Dying);
+            }
+            GameEvent::PlayerRespawned { from, to } if !caught => {
+                let player_entity = resourcemap.0[from.1 as usize][from.0 as usize]
+                    .pop()
+                    .expect("The player didnt exist?!");
+                let transform =
+                    Transform::from_translation(tile_translation(window, rows, to, 0.0));
+                glide(
+                    &mut commands,
+                    &mut movers,
+                    player_entity.1,
+                    transform,
+                    tile,
+                    player_glide,
+                );
+                resourcemap.0[to.1 as usize][to.0 as usize].push((transform, player_entity.1));
+            }
+            GameEvent::PlayerRespawned { .. } => (),
+            GameEvent::ExtraLife => info!("Extra life! {} left", game.state.lives),
+            GameEvent::GhostMoved { .. }
+            | GameEvent::GhostModeChanged(_)
+            | GameEvent::GhostsFrightened
+            | GameEvent::FrightEnded
+            | GameEvent::GhostRevived { .. } => (),
+            GameEvent::GameOver if !caught => next_state.set(AppState::GameOver),
+            GameEvent::GameOver => (),
+            // The new level may be a different size, so everything is rebuilt
+            // from the game as it is now, and nothing after it refers to the
+            // old entities.
+            GameEvent::Reset => {
+                info!("Restarted");
+                table.recorded = false;
+                next_state.set(AppState::Restarting);
+                break;
+            }
+            GameEvent::LevelStarted => {
+                info!("Starting level {}", game.state.level);
+                next_state.set(AppState::Restarting);
+                break;
+            }
+        }
+    }
 }
 
-fn start_state() -> GameState {
-    return GameState {
-        current_direction: None,
-        player_position: (2, 10),
-        scores: Scores {
-            high_score: 0,
-            current_score: 0,
-        },
-        ghost_1_cur_dir: Some(MoveDirection::Left),
-        ghost_1_pos: (9, 10),
-        num_moves_ghost_1_in_dir: 0,
+/// Whether no state change is waiting to happen. Several ticks can run in
+/// one frame, and once one has asked to leave `Playing`, say for a death, the
+/// rest wait for that to go through.
+fn state_settled(next_state: Res<NextState<AppState>>) -> bool {
+    matches!(*next_state, NextState::Unchanged)
+}
+
+/// Game speed multipliers `[` and `]` step through, for slowing things down
+/// or hurrying them along while debugging.
+const GAME_SPEEDS: [f32; 7] = [0.125, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+/// `[` slows the whole game down, `]` speeds it up and `\` puts it back to
+/// normal. Ticks, gliding and animations all run on [`Virtual`] time, so
+/// they all keep in step.
+fn change_game_speed(keys: Res<ButtonInput<KeyCode>>, mut time: ResMut<Time<Virtual>>) {
+    let current = GAME_SPEEDS
+        .iter()
+        .position(|&speed| speed >= time.relative_speed())
+        .unwrap_or(3);
+    let index = if keys.just_pressed(KeyCode::BracketLeft) {
+        current.saturating_sub(1)
+    } else if keys.just_pressed(KeyCode::BracketRight) {
+        (current + 1).min(GAME_SPEEDS.len() - 1)
+    } else if keys.just_pressed(KeyCode::Backslash) {
+        3
+    } else {
+        return;
     };
+    time.set_relative_speed(GAME_SPEEDS[index]);
+    info!("Game speed x{}", GAME_SPEEDS[index]);
+}
+
+/// Sokoban is turn based, so it steps as soon as a move or undo is read
+/// instead of waiting for the next timer tick.
+fn turn_based_input(game: Res<Game>, pending: Res<PendingCommands>) -> bool {
+    game.rules == Rules::Sokoban && !pending.0.is_empty()
+}
+
+/// Sends every ghost entity gliding to the tile its ghost is on and draws it
+/// the way its state calls for: blue while frightened, flashing white for the
+/// last couple of seconds, and just eyes once eaten.
+fn sync_ghosts(
+    game: Res<Game>,
+    assets: Res<TileAssets>,
+    time: Res<Time<Virtual>>,
+    window_query: Query<&Window>,
+    mut ghosts: Query<(
+        &Ghost,
+        &Transform,
+        &mut Motion,
+        &mut Mesh2dHandle,
+        &mut Handle<ColorMaterial>,
+    )>,
+) {
+    let window = window_query.single();
+    let rows = game.tilemap.height();
+    let tile = window.height() / rows as f32;
+    let frightened_ticks = game.state.ghosts.frightened_ticks;
+    let flashing = frightened_ticks <= 2 * TICKS_PER_SECOND && frightened_ticks % 2 == 1;
+    for (ghost, transform, mut motion, mut mesh, mut material) in &mut ghosts {
+        let Some(state) = game.state.ghosts.list.get(ghost.0) else {
+            continue;
+        };
+        let target = Transform::from_translation(tile_translation(window, rows, state.pos, 0.5));
+        if motion.to.translation != target.translation {
+            let duration = glide_time(game.ghost_speed(ghost.0));
+            motion.retarget(transform, target, tile, time.elapsed_seconds(), duration);
+        }
+        let (new_mesh, new_material) = match state.state {
+            GhostState::Active => (&assets.ghost, &ghost.1),
+            GhostState::Frightened if flashing => (&assets.ghost, &assets.white),
+            GhostState::Frightened => (&assets.ghost, &assets.blue),
+            GhostState::Eyes => (&assets.eyes, &assets.white),
+        };
+        if mesh.0 != new_mesh.0 {
+            *mesh = new_mesh.clone();
+        }
+        if *material != *new_material {
+            *material = new_material.clone();
+        }
+    }
+}
+
+/// Score, lives, level and pellets left, drawn over the top right corner,
+/// along with the game speed when it isn't normal.
+fn hud(mut contexts: EguiContexts, game: Res<Game>, time: Res<Time<Virtual>>) {
+    let state = &game.state;
+    egui::Area::new(egui::Id::new("hud"))
+        .anchor(egui::Align2::RIGHT_TOP, [-12.0, 12.0])
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                egui::Grid::new("hud_grid").show(ui, |ui| {
+                    let mut row = |label: &str, value: String| {
+                        ui.label(label);
+                        ui.strong(value);
+                        ui.end_row();
+                    };
+                    row("Score", state.scores.current_score.to_string());
+                    row("High score", state.scores.high_score.to_string());
+                    row("Lives", state.lives.to_string());
+                    row("Level", state.level.to_string());
+                    if game.rules == Rules::Sokoban {
+                        row("Moves", state.moves.to_string());
+                        row("Pushes", state.pushes.to_string());
+                    } else {
+                        row("Pellets left", game.pellets_left().to_string());
+                    }
+                    if time.relative_speed() != 1.0 {
+                        row("Speed", format!("x{}", time.relative_speed()));
+                    }
+                });
+            });
+        });
 }
 
 fn dir_to_int(dir: MoveDirection) -> i32 {
@@ -304,26 +818,49 @@ fn dir_to_int(dir: MoveDirection) -> i32 {
 fn calc_rotation(next_dir: MoveDirection) -> Quat {
     let next_dir_int = dir_to_int(next_dir);
 
-    return Quat::from_rotation_z(((next_dir_int) as f32 * 90.0).to_radians());
+    Quat::from_rotation_z(((next_dir_int) as f32 * 90.0).to_radians())
 }
 
+/// Handles the commands bound to the inputs pressed this frame, and the
+/// moves from the touch screen, unless a replay is being played back
+/// instead. Every command is also recorded if a recording is running.
+#[allow(clippy::too_many_arguments)]
 fn text_input(
-    mut gamestate: ResMut<GameState>,
+    mut pending: ResMut<PendingCommands>,
     mut next_state: ResMut<NextState<AppState>>,
-    keys: Res<ButtonInput<KeyCode>>,
+    mut pressed: EventReader<InputPressed>,
+    mut touch_moves: EventReader<TouchMove>,
+    bindings: Res<InputBindings>,
+    ticks: Res<Ticks>,
+    playback: Option<Res<Playback>>,
+    mut recording: Option<ResMut<Recording>>,
+    game: Res<Game>,
+    mut table: ResMut<HighScoreTable>,
 ) {
-    let cmd = read_input(keys);
-    match cmd {
-        Some(cmd) => match cmd {
-            Command::Move(dir) => {
-                gamestate.current_direction = Some(dir);
-            }
+    if playback.is_some() {
+        return;
+    }
+    let bound = pressed
+        .read()
+        .filter_map(|InputPressed(source)| bindings.command(&source.name()));
+    let touched = touch_moves
+        .read()
+        .map(|TouchMove(direction)| Command::Move(*direction));
+    for cmd in bound.chain(touched) {
+        if let Some(recording) = recording.as_deref_mut() {
+            recording.record(ticks.0, cmd);
+        }
+        match cmd {
+            Command::Move(_) | Command::Undo | Command::Redo => pending.0.push(cmd),
             Command::Quit => next_state.set(AppState::GameOver),
+            // Resets are read on the game over screen too, which has to go
+            // back to playing for the next tick to step it.
             Command::Reset => {
-                next_state.set(AppState::Restarting);
+                save_high_score(&game, &mut table);
+                pending.0.push(cmd);
+                next_state.set(AppState::Playing);
             }
-        },
-        None => (),
+        }
     }
 }
 
@@ -333,11 +870,10 @@ struct MyCameraMarker;
 #[derive(Component)]
 struct Pacman;
 
+/// Draws the ghost at this index of `GameState::ghosts`, in its own colour
+/// while it isn't frightened or eaten.
 #[derive(Component)]
-struct AnimationIndices {
-    first: usize,
-    last: usize,
-}
+struct Ghost(usize, Handle<ColorMaterial>);
 
 #[derive(Component, Deref, DerefMut)]
 struct AnimationTimer(Timer);
@@ -375,40 +911,140 @@ struct ResourceMap(pub Vec<Vec<Vec<(Transform, Entity)>>>);
 #[derive(Resource)]
 struct PacmanMeshes(pub Vec<Mesh2dHandle>);
 
-fn create_resources(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    window: Query<&Window>,
-    tilemap: Res<Tilemap>,
-    mut resourcemap: ResMut<ResourceMap>,
-    mut pacmans: ResMut<PacmanMeshes>,
-) {
-    let window = window.single();
+/// Frames of the player's death, a sector closing up until nothing is left.
+#[derive(Resource)]
+struct DeathMeshes(pub Vec<Mesh2dHandle>);
 
-    let border_len = (window.height()) / (tilemap.0.len() as f32);
+/// The frame of [`DeathMeshes`] showing next.
+#[derive(Resource, Default)]
+struct DeathFrame(usize);
 
-    let starting_mesh = Mesh2dHandle(meshes.add(CircularSector::new(border_len / 3.0, 3.5)));
-    pacmans.0.append(&mut vec![
-        Mesh2dHandle(meshes.add(CircularSector::new(border_len / 3.0, 2.5))),
-        Mesh2dHandle(meshes.add(CircularSector::new(border_len / 3.0, 2.65))),
-        Mesh2dHandle(meshes.add(CircularSector::new(border_len / 3.0, 2.8))),
-        starting_mesh.clone(),
-    ]);
-    let wall = Mesh2dHandle(meshes.add(Rectangle::new(border_len, border_len)));
-    let pacman = starting_mesh.clone();
-    let food = Mesh2dHandle(meshes.add(Circle {
-        radius: (border_len / 10.0),
-    }));
+/// Meshes and materials the tiles are drawn with, kept around so tiles can
+/// be spawned again after the map changes under them.
+#[derive(Resource)]
+struct TileAssets {
+    wall: Mesh2dHandle,
+    pacman: Mesh2dHandle,
+    food: Mesh2dHandle,
+    power_pellet: Mesh2dHandle,
+    crate_mesh: Mesh2dHandle,
+    goal: Mesh2dHandle,
+    ghost: Mesh2dHandle,
+    eyes: Mesh2dHandle,
+    blue: Handle<ColorMaterial>,
+    white: Handle<ColorMaterial>,
+    yellow: Handle<ColorMaterial>,
+    green: Handle<ColorMaterial>,
+    brown: Handle<ColorMaterial>,
+    /// One colour per ghost personality, in [`Personality::ORDER`].
+    ghosts: [Handle<ColorMaterial>; 4],
+}
 
-    let top_left = Vec3::new(-window.width() / 2.0, window.height() / 2.0, 0.0);
-    let yellow = Color::linear_rgb(255.0, 255.0, 0.0);
-    let blue = Color::linear_rgb(0.0, 0.0, 255.0);
-    let mesh_default = default();
-    let blue_handle = materials.add(blue);
-    let yellow_handle = materials.add(yellow);
+impl TileAssets {
+    fn ghost_material(&self, personality: Personality) -> Handle<ColorMaterial> {
+        let index = Personality::ORDER
+            .iter()
+            .position(|p| *p == personality)
+            .unwrap_or(0);
+        self.ghosts[index].clone()
+    }
+}
 
-    resourcemap.0 = tilemap
+/// Spawns the entity that draws `glyph` on the tile at `pos`, if that glyph
+/// is drawn at all.
+fn spawn_glyph(
+    commands: &mut Commands,
+    assets: &TileAssets,
+    window: &Window,
+    rows: usize,
+    pos: (i32, i32),
+    glyph: char,
+) -> Option<(Transform, Entity)> {
+    let translation = |z| tile_translation(window, rows, pos, z);
+    let (mesh, material, transform) = match glyph {
+        c if WALLS.contains(&c) => Some((
+            assets.wall.clone(),
+            assets.blue.clone(),
+            Transform::from_translation(translation(0.0)),
+        )),
+        PELLET => Some((
+            assets.food.clone(),
+            assets.yellow.clone(),
+            Transform::from_translation(translation(-1.0)),
+        )),
+        POWER_PELLET => Some((
+            assets.power_pellet.clone(),
+            assets.yellow.clone(),
+            Transform::from_translation(translation(-1.0)),
+        )),
+        GOAL => Some((
+            assets.goal.clone(),
+            assets.green.clone(),
+            Transform::from_translation(translation(-1.0)),
+        )),
+        BOX => Some((
+            assets.crate_mesh.clone(),
+            assets.brown.clone(),
+            Transform::from_translation(translation(0.0)),
+        )),
+        PLAYER => Some((
+            assets.pacman.clone(),
+            assets.yellow.clone(),
+            Transform {
+                translation: translation(0.0),
+                rotation: calc_rotation(MoveDirection::Right),
+                ..default()
+            },
+        )),
+        _ => None,
+    }?;
+    let entity = MaterialMesh2dBundle {
+        mesh,
+        material,
+        transform,
+        ..default()
+    };
+    if glyph == PLAYER {
+        let entity_commands = commands.spawn((
+            AnimationTimer(Timer::from_seconds(0.0625, TimerMode::Repeating)),
+            entity,
+            Pacman,
+            Motion::at_rest(transform),
+        ));
+        Some((transform, entity_commands.id()))
+    } else {
+        let entity_commands = commands.spawn(entity);
+        Some((transform, entity_commands.id()))
+    }
+}
+
+/// Spawns the ghosts and every drawn glyph of the map, returning the map's
+/// entities laid out as a [`ResourceMap`].
+fn spawn_level(
+    commands: &mut Commands,
+    assets: &TileAssets,
+    window: &Window,
+    sim: &Simulation,
+) -> Vec<Vec<Vec<(Transform, Entity)>>> {
+    let tilemap = &sim.tilemap;
+    let rows = tilemap.height();
+
+    for (index, ghost) in sim.state.ghosts.list.iter().enumerate() {
+        let material = assets.ghost_material(ghost.personality);
+        let transform = Transform::from_translation(tile_translation(window, rows, ghost.pos, 0.5));
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: assets.ghost.clone(),
+                material: material.clone(),
+                transform,
+                ..default()
+            },
+            Ghost(index, material),
+            Motion::at_rest(transform),
+        ));
+    }
+
+    tilemap
         .0
         .iter()
         .enumerate()
@@ -416,54 +1052,125 @@ fn create_resources(
             row.iter()
                 .enumerate()
                 .map(|(col_idx, tile)| {
-                    let cell = tile[0];
-                    let x = col_idx as f32 * border_len + (border_len / 2.0);
-                    let y = -(row_idx as f32 * border_len) - (border_len / 2.0);
-                    let Some((mesh, material, transform)) = (match cell {
-                        '|' | '_' | '-' | '#' => Some((
-                            wall.clone(),
-                            blue_handle.clone(),
-                            Transform::from_translation(top_left + Vec3::new(x, y, 0.0)),
-                        )),
-                        '•' => Some((
-                            food.clone(),
-                            yellow_handle.clone(),
-                            Transform::from_translation(top_left + Vec3::new(x, y, -1.0)),
-                        )),
-                        'K' => Some((
-                            pacman.clone(),
-                            yellow_handle.clone(),
-                            Transform {
-                                translation: top_left + Vec3::new(x, y, 0.0),
-                                rotation: calc_rotation(MoveDirection::Right),
-                                ..default()
-                            },
-                        )),
-                        _ => None,
-                    }) else {
-                        return vec![];
-                    };
-                    let entity = MaterialMesh2dBundle {
-                        mesh,
-                        material,
-                        transform,
-                        ..mesh_default
-                    };
-                    if cell == 'K' {
-                        let entity_commands = commands.spawn((
-                            AnimationTimer(Timer::from_seconds(0.0625, TimerMode::Repeating)),
-                            entity,
-                            Pacman,
-                        ));
-                        return vec![(transform, entity_commands.id())];
-                    } else {
-                        let entity_commands = commands.spawn(entity);
-                        return vec![(transform, entity_commands.id())];
-                    }
+                    let pos = (col_idx as i32, row_idx as i32);
+                    tile.iter()
+                        .filter_map(|&glyph| {
+                            spawn_glyph(commands, assets, window, rows, pos, glyph)
+                        })
+                        .collect::<Vec<(Transform, Entity)>>()
                 })
                 .collect::<Vec<Vec<(Transform, Entity)>>>()
         })
-        .collect::<Vec<Vec<Vec<(Transform, Entity)>>>>();
+        .collect::<Vec<Vec<Vec<(Transform, Entity)>>>>()
+}
+
+/// Frames in the death animation, shown at the pace of the player's chomp.
+const DEATH_FRAMES: usize = 16;
+
+/// Builds the meshes and materials for a map `rows` tiles tall, sized so it
+/// fills the window, along with the frames of the player's chomp.
+fn build_assets(
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<ColorMaterial>,
+    window: &Window,
+    rows: usize,
+) -> (TileAssets, PacmanMeshes, DeathMeshes) {
+    let border_len = (window.height()) / (rows as f32);
+
+    let starting_mesh = Mesh2dHandle(meshes.add(CircularSector::new(border_len / 3.0, 3.5)));
+    let pacmans = PacmanMeshes(vec![
+        Mesh2dHandle(meshes.add(CircularSector::new(border_len / 3.0, 2.5))),
+        Mesh2dHandle(meshes.add(CircularSector::new(border_len / 3.0, 2.65))),
+        Mesh2dHandle(meshes.add(CircularSector::new(border_len / 3.0, 2.8))),
+        starting_mesh.clone(),
+    ]);
+    let deaths = DeathMeshes(
+        (1..=DEATH_FRAMES)
+            .map(|frame| {
+                let angle = 3.5 * (DEATH_FRAMES - frame) as f32 / DEATH_FRAMES as f32;
+                Mesh2dHandle(meshes.add(CircularSector::new(border_len / 3.0, angle)))
+            })
+            .collect(),
+    );
+    let assets = TileAssets {
+        wall: Mesh2dHandle(meshes.add(Rectangle::new(border_len, border_len))),
+        pacman: starting_mesh.clone(),
+        food: Mesh2dHandle(meshes.add(Circle {
+            radius: (border_len / 10.0),
+        })),
+        power_pellet: Mesh2dHandle(meshes.add(Circle {
+            radius: (border_len / 4.0),
+        })),
+        crate_mesh: Mesh2dHandle(meshes.add(Rectangle::new(border_len * 0.8, border_len * 0.8))),
+        goal: Mesh2dHandle(meshes.add(Rectangle::new(border_len / 3.0, border_len / 3.0))),
+        ghost: Mesh2dHandle(meshes.add(Circle {
+            radius: (border_len / 3.0),
+        })),
+        eyes: Mesh2dHandle(meshes.add(Circle {
+            radius: (border_len / 8.0),
+        })),
+        blue: materials.add(Color::linear_rgb(0.0, 0.0, 255.0)),
+        white: materials.add(Color::WHITE),
+        yellow: materials.add(Color::linear_rgb(255.0, 255.0, 0.0)),
+        green: materials.add(Color::linear_rgb(0.0, 255.0, 0.0)),
+        brown: materials.add(Color::srgb(0.55, 0.35, 0.15)),
+        ghosts: Personality::ORDER.map(|personality| {
+            materials.add(match personality {
+                Personality::Chaser => Color::srgb(1.0, 0.0, 0.0),
+                Personality::Ambusher => Color::srgb(1.0, 0.72, 1.0),
+                Personality::Flanker => Color::srgb(0.0, 1.0, 1.0),
+                Personality::Shy => Color::srgb(1.0, 0.72, 0.32),
+            })
+        }),
+    };
+
+    (assets, pacmans, deaths)
+}
+
+/// Despawns the map's and ghosts' entities and spawns them again from `sim`.
+fn replace_level(
+    commands: &mut Commands,
+    resourcemap: &mut ResourceMap,
+    ghosts: &Query<Entity, With<Ghost>>,
+    assets: &TileAssets,
+    window: &Window,
+    sim: &Simulation,
+) {
+    for entity in resourcemap.0.drain(..).flatten().flatten().map(|(_, e)| e) {
+        commands.entity(entity).despawn();
+    }
+    for entity in ghosts {
+        commands.entity(entity).despawn();
+    }
+    resourcemap.0 = spawn_level(commands, assets, window, sim);
+}
+
+/// Despawns every entity drawing the map and spawns them again from the
+/// [`Game`]. Runs once at startup and then whenever a [`RespawnLevel`] is
+/// sent, rebuilding the assets too in case the new map is a different size.
+fn respawn_level(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    window: Query<&Window>,
+    game: Res<Game>,
+    mut resourcemap: ResMut<ResourceMap>,
+    ghosts: Query<Entity, With<Ghost>>,
+) {
+    let window = window.single();
+    let (assets, pacmans, deaths) =
+        build_assets(&mut meshes, &mut materials, window, game.tilemap.height());
+    replace_level(
+        &mut commands,
+        &mut resourcemap,
+        &ghosts,
+        &assets,
+        window,
+        &game,
+    );
+    commands.insert_resource(assets);
+    commands.insert_resource(pacmans);
+    commands.insert_resource(deaths);
 }
 
 fn setup_camera(mut commands: Commands) {
@@ -476,6 +1183,578 @@ fn setup_camera(mut commands: Commands) {
     ));
 }
 
+/// Puts the score just finished on the high score table when the game ends.
+fn record_high_score(game: Res<Game>, mut table: ResMut<HighScoreTable>) {
+    save_high_score(&game, &mut table);
+}
+
+/// Puts the score of `game` on the high score table if it made it and isn't
+/// there already, and saves the table. Games given up on before they are
+/// over go through here too, so their scores count as well.
+fn save_high_score(game: &Simulation, table: &mut HighScoreTable) {
+    if std::mem::replace(&mut table.recorded, true) {
+        return;
+    }
+    let score = game.state.scores.current_score;
+    if score <= 0 {
+        return;
+    }
+    let name = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "Player".to_string());
+    let date = web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let entry = HighScore {
+        name,
+        score,
+        date,
+        level: game.state.level,
+        seed: Some(game.rng.seed()),
+    };
+    let Some(rank) = table.scores.insert(entry) else {
+        return;
+    };
+    info!("New high score {score}, number {} on the table", rank + 1);
+    if let Err(err) = table.storage.save(&table.scores) {
+        warn!("Couldn't save high scores: {err}");
+    }
+}
+
+/// Lists the high score table, best first.
+fn high_score_list(ui: &mut egui::Ui, scores: &HighScores) {
+    if scores.entries().is_empty() {
+        ui.label("No high scores yet.");
+        return;
+    }
+    egui::Grid::new("high scores").show(ui, |ui| {
+        for (rank, entry) in scores.entries().iter().enumerate() {
+            ui.label(format!("{}.", rank + 1));
+            ui.label(&entry.name);
+            ui.label(entry.score.to_string());
+            ui.label(format!("Level {}", entry.level));
+            ui.end_row();
+        }
+    });
+}
+
+/// Hides the ghosts and starts the death animation from its first frame.
+fn start_dying(mut commands: Commands, mut ghosts: Query<&mut Visibility, With<Ghost>>) {
+    for mut visibility in &mut ghosts {
+        *visibility = Visibility::Hidden;
+    }
+    commands.insert_resource(DeathFrame::default());
+}
+
+/// Plays the death animation on the player's entity, then respawns every
+/// entity where the simulation put it back, or ends the game if that was the
+/// last life.
+#[allow(clippy::too_many_arguments)]
+fn animate_death(
+    time: Res<Time>,
+    game: Res<Game>,
+    deaths: Res<DeathMeshes>,
+    mut frame: ResMut<DeathFrame>,
+    mut pacman: Query<(Entity, &mut AnimationTimer), With<Pacman>>,
+    mut commands: Commands,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut respawn: EventWriter<RespawnLevel>,
+) {
+    let Ok((entity, mut timer)) = pacman.get_single_mut() else {
+        return;
+    };
+    if !timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    if let Some(mesh) = deaths.0.get(frame.0) {
+        commands.entity(entity).insert(mesh.clone());
+        frame.0 += 1;
+    } else if game.state.game_over {
+        next_state.set(AppState::GameOver);
+    } else {
+        respawn.send(RespawnLevel);
+        next_state.set(AppState::Playing);
+    }
+}
+
+/// Rebuilds every entity for the game after a reset or a new level and
+/// carries on.
+fn restart_game(
+    mut next_state: ResMut<NextState<AppState>>,
+    mut respawn: EventWriter<RespawnLevel>,
+) {
+    respawn.send(RespawnLevel);
+    next_state.set(AppState::Playing);
+}
+
+/// Replaces the game with a new one starting on level `first` of its list,
+/// with `lives` lives. The seed and high score carry over, and a recording
+/// starts over with the new game.
+fn new_game(
+    game: &mut Game,
+    first: usize,
+    lives: i32,
+    ticks: &mut Ticks,
+    recording: Option<&mut Recording>,
+) {
+    game.levels.first = first;
+    game.starting_lives = lives;
+    game.reset();
+    ticks.0 = 0;
+    if let Some(recording) = recording {
+        recording.restart(first, lives);
+    }
+}
+
+/// Restarts the game from a menu through [`Command::Reset`], recording it as
+/// if it had been pressed.
+fn send_reset(
+    pending: &mut PendingCommands,
+    next_state: &mut NextState<AppState>,
+    ticks: &Ticks,
+    recording: Option<&mut Recording>,
+) {
+    if let Some(recording) = recording {
+        recording.record(ticks.0, Command::Reset);
+    }
+    pending.0.push(Command::Reset);
+    next_state.set(AppState::Playing);
+}
+
+/// A window in the middle of the screen for the menus to draw into.
+fn menu_window(title: &str) -> egui::Window<'_> {
+    egui::Window::new(title)
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .collapsible(false)
+        .resizable(false)
+}
+
+/// A full width menu button, focused when nothing else is so the menu can be
+/// driven with Tab and Enter straight away.
+fn menu_button(ui: &mut egui::Ui, text: &str) -> bool {
+    let response = ui.add_sized([160.0, 24.0], egui::Button::new(text));
+    if ui.memory(|memory| memory.focused().is_none()) {
+        response.request_focus();
+    }
+    response.clicked()
+}
+
+/// The title screen: start a game, pick up the one in progress, choose the
+/// level to start on, change settings, or quit.
+#[allow(clippy::too_many_arguments)]
+fn main_menu(
+    mut contexts: EguiContexts,
+    mut menu: ResMut<Menu>,
+    mut game: ResMut<Game>,
+    mut settings: ResMut<Settings>,
+    mut bindings: ResMut<InputBindings>,
+    mut wireframe: ResMut<Wireframe2dConfig>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut pressed: EventReader<InputPressed>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut respawn: EventWriter<RespawnLevel>,
+    mut exit: EventWriter<AppExit>,
+    mut ticks: ResMut<Ticks>,
+    mut recording: Option<ResMut<Recording>>,
+    playback: Option<Res<Playback>>,
+    mut table: ResMut<HighScoreTable>,
+) {
+    menu_window("Main menu").show(contexts.ctx_mut(), |ui| match menu.page {
+        MenuPage::Main => {
+            // A replay plays the game it recorded and no other.
+            if ui
+                .add_enabled_ui(playback.is_none(), |ui| menu_button(ui, "Start"))
+                .inner
+            {
+                save_high_score(&game, &mut table);
+                let first = menu.level;
+                new_game(
+                    &mut game,
+                    first,
+                    settings.lives,
+                    &mut ticks,
+                    recording.as_deref_mut(),
+                );
+                table.recorded = false;
+                menu.paused_game = false;
+                respawn.send(RespawnLevel);
+                next_state.set(AppState::Playing);
+            }
+            let paused_game = menu.paused_game;
+            if ui
+                .add_enabled_ui(paused_game, |ui| menu_button(ui, "Continue"))
+                .inner
+            {
+                menu.paused_game = false;
+                next_state.set(AppState::Playing);
+            }
+            if menu_button(ui, "Level select") {
+                menu.page = MenuPage::LevelSelect;
+            }
+            if menu_button(ui, "High scores") {
+                menu.page = MenuPage::HighScores;
+            }
+            if menu_button(ui, "Settings") {
+                menu.page = MenuPage::Settings;
+            }
+            if menu_button(ui, "Quit") {
+                save_high_score(&game, &mut table);
+                exit.send(AppExit::Success);
+            }
+        }
+        MenuPage::LevelSelect => {
+            for (index, level) in game.levels.levels.iter().enumerate() {
+                let name = if level.name.is_empty() {
+                    format!("Level {}", index + 1)
+                } else {
+                    format!("{}. {}", index + 1, level.name)
+                };
+                ui.radio_value(&mut menu.level, index, name);
+            }
+            ui.separator();
+            if menu_button(ui, "Back") {
+                menu.page = MenuPage::Main;
+            }
+        }
+        MenuPage::HighScores => {
+            high_score_list(ui, &table.scores);
+            ui.separator();
+            if menu_button(ui, "Back") {
+                menu.page = MenuPage::Main;
+            }
+        }
+        MenuPage::Settings => {
+            ui.add(egui::Slider::new(&mut settings.lives, 1..=9).text("Lives"));
+            ui.checkbox(&mut wireframe.global, "Wireframe");
+            ui.separator();
+            if menu_button(ui, "Controls") {
+                menu.page = MenuPage::Controls;
+            }
+            if menu_button(ui, "Back") {
+                menu.page = MenuPage::Main;
+            }
+        }
+        MenuPage::Controls => controls_page(ui, &mut menu, &mut bindings, &keys, &mut pressed),
+    });
+}
+
+/// Keys with a job of their own, which can't be bound to a command.
+const RESERVED_KEYS: [(KeyCode, &str); 8] = [
+    (KeyCode::Escape, "pause"),
+    (KeyCode::KeyP, "pause"),
+    (KeyCode::Space, "wireframe"),
+    (KeyCode::F5, "save"),
+    (KeyCode::F9, "resume"),
+    (KeyCode::BracketLeft, "slowing down"),
+    (KeyCode::BracketRight, "speeding up"),
+    (KeyCode::Backslash, "normal speed"),
+];
+
+/// Lists what every command is bound to. Clicking an input unbinds it, and
+/// `+` binds the next input pressed. Inputs bound to more than one command
+/// are shown in red, and the bindings can't be saved until they are sorted
+/// out.
+fn controls_page(
+    ui: &mut egui::Ui,
+    menu: &mut Menu,
+    bindings: &mut Bindings,
+    keys: &ButtonInput<KeyCode>,
+    pressed: &mut EventReader<InputPressed>,
+) {
+    if let Some(capture) = &mut menu.capture {
+        ui.label(format!(
+            "Press a key or button for {}, or Escape to cancel.",
+            command_name(capture.command)
+        ));
+        if let Some(refused) = &capture.refused {
+            ui.colored_label(ui.visuals().error_fg_color, refused);
+        }
+        for InputPressed(source) in pressed.read() {
+            if let InputSource::Key(key) = source {
+                if let Some(index) = capture.held.iter().position(|held| held == key) {
+                    capture.held.swap_remove(index);
+                    continue;
+                }
+                if *key == KeyCode::Escape {
+                    menu.capture = None;
+                    return;
+                }
+                if let Some((_, job)) = RESERVED_KEYS.iter().find(|(reserved, _)| reserved == key) {
+                    capture.refused = Some(format!("{} is kept for {job}.", source.name()));
+                    continue;
+                }
+            }
+            bindings.bind(capture.command, &source.name());
+            menu.capture = None;
+            return;
+        }
+        return;
+    }
+    pressed.clear();
+
+    let conflicts = bindings.conflicts();
+    egui::Grid::new("controls").show(ui, |ui| {
+        for command in COMMANDS {
+            ui.label(command_name(command));
+            ui.horizontal(|ui| {
+                for input in bindings.inputs(command).to_vec() {
+                    let mut text = egui::RichText::new(&input);
+                    if conflicts.iter().any(|conflict| conflict.input == input) {
+                        text = text.color(ui.visuals().error_fg_color);
+                    }
+                    if ui.small_button(text).on_hover_text("Unbind").clicked() {
+                        bindings.unbind(command, &input);
+                    }
+                }
+                if ui.small_button("+").on_hover_text("Bind").clicked() {
+                    menu.capture = Some(Capture {
+                        command,
+                        held: keys.get_pressed().copied().collect(),
+                        refused: None,
+                    });
+                }
+            });
+            ui.end_row();
+        }
+    });
+    for conflict in &conflicts {
+        ui.colored_label(ui.visuals().error_fg_color, conflict.to_string());
+    }
+    ui.separator();
+    if ui
+        .add_enabled_ui(conflicts.is_empty(), |ui| menu_button(ui, "Save"))
+        .inner
+    {
+        match std::fs::write(BINDINGS_PATH, bindings.to_text()) {
+            Ok(()) => info!("Saved controls to {BINDINGS_PATH}"),
+            Err(err) => warn!("Couldn't save controls to {BINDINGS_PATH}: {err}"),
+        }
+    }
+    if menu_button(ui, "Defaults") {
+        *bindings = Bindings::default();
+    }
+    if menu_button(ui, "Back") {
+        menu.page = MenuPage::Settings;
+    }
+}
+
+/// Escape or P pauses the game and resumes it again.
+fn toggle_pause(
+    keys: Res<ButtonInput<KeyCode>>,
+    state: Res<State<AppState>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if !(keys.just_released(KeyCode::Escape) || keys.just_released(KeyCode::KeyP)) {
+        return;
+    }
+    match state.get() {
+        AppState::Playing => next_state.set(AppState::Paused),
+        AppState::Paused => next_state.set(AppState::Playing),
+        _ => (),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pause_menu(
+    mut contexts: EguiContexts,
+    mut menu: ResMut<Menu>,
+    mut pending: ResMut<PendingCommands>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exit: EventWriter<AppExit>,
+    ticks: Res<Ticks>,
+    mut recording: Option<ResMut<Recording>>,
+    playback: Option<Res<Playback>>,
+    game: Res<Game>,
+    mut table: ResMut<HighScoreTable>,
+) {
+    menu_window("Paused").show(contexts.ctx_mut(), |ui| {
+        if menu_button(ui, "Continue") {
+            next_state.set(AppState::Playing);
+        }
+        if ui
+            .add_enabled_ui(playback.is_none(), |ui| menu_button(ui, "Restart"))
+            .inner
+        {
+            save_high_score(&game, &mut table);
+            send_reset(
+                &mut pending,
+                &mut next_state,
+                &ticks,
+                recording.as_deref_mut(),
+            );
+        }
+        if menu_button(ui, "Main menu") {
+            menu.paused_game = true;
+            menu.page = MenuPage::Main;
+            next_state.set(AppState::MainMenu);
+        }
+        if menu_button(ui, "Quit") {
+            save_high_score(&game, &mut table);
+            exit.send(AppExit::Success);
+        }
+    });
+}
+
+/// The final score, and a way back into the game.
+#[allow(clippy::too_many_arguments)]
+fn game_over_screen(
+    mut contexts: EguiContexts,
+    game: Res<Game>,
+    mut pending: ResMut<PendingCommands>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exit: EventWriter<AppExit>,
+    ticks: Res<Ticks>,
+    mut recording: Option<ResMut<Recording>>,
+    playback: Option<Res<Playback>>,
+    table: Res<HighScoreTable>,
+) {
+    let scores = game.state.scores;
+    menu_window("Game over").show(contexts.ctx_mut(), |ui| {
+        ui.heading(format!("Final score {}", scores.current_score));
+        if scores.current_score > 0 && scores.current_score == scores.high_score {
+            ui.label("New high score!");
+        } else {
+            ui.label(format!("High score {}", scores.high_score));
+        }
+        ui.separator();
+        high_score_list(ui, &table.scores);
+        ui.separator();
+        if ui
+            .add_enabled_ui(playback.is_none(), |ui| menu_button(ui, "Play again"))
+            .inner
+        {
+            send_reset(
+                &mut pending,
+                &mut next_state,
+                &ticks,
+                recording.as_deref_mut(),
+            );
+        }
+        if menu_button(ui, "Main menu") {
+            next_state.set(AppState::MainMenu);
+        }
+        if menu_button(ui, "Quit") {
+            exit.send(AppExit::Success);
+        }
+    });
+}
+
+/// Leaves the cleared maze up for a moment before the next level starts.
+fn start_level_complete(mut commands: Commands) {
+    commands.insert_resource(LevelCompleteTimer(Timer::from_seconds(
+        2.0,
+        TimerMode::Once,
+    )));
+}
+
+/// Once the pause is over, goes back to playing, and the next tick starts the
+/// next of the game's levels with the score and lives carried over.
+fn advance_level(
+    time: Res<Time>,
+    mut timer: ResMut<LevelCompleteTimer>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    if timer.tick(time.delta()).finished() {
+        next_state.set(AppState::Playing);
+    }
+}
+
+/// Where F5 saves the game and F9 resumes it from.
+#[cfg(feature = "serde")]
+const SAVE_PATH: &str = "savegame.json";
+
+/// F5 saves the game in progress; F9 throws away the current one and resumes
+/// the saved game, rebuilding every entity from it. A replay can't start from
+/// a saved game, so F9 does nothing while one plays back and ends a
+/// recording.
+#[cfg(feature = "serde")]
+#[allow(clippy::too_many_arguments)]
+fn save_and_resume(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut respawn: EventWriter<RespawnLevel>,
+    mut menu: ResMut<Menu>,
+    mut table: ResMut<HighScoreTable>,
+    recording: Option<Res<Recording>>,
+    playback: Option<Res<Playback>>,
+    mut commands: Commands,
+) {
+    if keys.just_released(KeyCode::F5) {
+        let saved = game
+            .to_save()
+            .map_err(|err| err.to_string())
+            .and_then(|json| std::fs::write(SAVE_PATH, json).map_err(|err| err.to_string()));
+        match saved {
+            Ok(()) => info!("Saved to {SAVE_PATH}"),
+            Err(err) => warn!("Couldn't save to {SAVE_PATH}: {err}"),
+        }
+    }
+    if !keys.just_released(KeyCode::F9) {
+        return;
+    }
+    if playback.is_some() {
+        warn!("Can't resume a saved game while playing a replay back");
+        return;
+    }
+    let loaded = std::fs::read_to_string(SAVE_PATH)
+        .map_err(|err| err.to_string())
+        .and_then(|json| Simulation::from_save(&json).map_err(|err| err.to_string()));
+    let mut sim = match loaded {
+        Ok(sim) => sim,
+        Err(err) => {
+            warn!("Couldn't resume from {SAVE_PATH}: {err}");
+            return;
+        }
+    };
+    sim.state.scores.high_score = sim
+        .state
+        .scores
+        .high_score
+        .max(game.state.scores.high_score);
+
+    save_high_score(&game, &mut table);
+    table.recorded = false;
+    menu.paused_game = false;
+    if let Some(recording) = recording {
+        info!("Stopped recording to {}", recording.path);
+        commands.remove_resource::<Recording>();
+    }
+    game.0 = sim;
+    respawn.send(RespawnLevel);
+    next_state.set(AppState::Playing);
+    info!("Resumed from {SAVE_PATH}");
+}
+
+/// Where high scores are kept: a file on native, `localStorage` in the
+/// browser, and nowhere without the `serde` feature.
+fn score_storage() -> Box<dyn ScoreStorage + Send + Sync> {
+    #[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+    return Box::new(mystuff::highscores::FileStorage {
+        path: "highscores.json".into(),
+    });
+    #[cfg(all(feature = "serde", target_arch = "wasm32"))]
+    return Box::new(mystuff::highscores::LocalStorage {
+        key: "highscores".to_string(),
+    });
+    #[cfg(not(feature = "serde"))]
+    Box::new(mystuff::highscores::NoStorage)
+}
+
+/// Loads the high score table, starting an empty one if it can't be read.
+fn load_high_scores() -> HighScoreTable {
+    let storage = score_storage();
+    let scores = storage.load().unwrap_or_else(|err| {
+        eprintln!("Couldn't load high scores: {err}");
+        HighScores::default()
+    });
+    HighScoreTable {
+        scores,
+        storage,
+        recorded: false,
+    }
+}
+
 fn toggle_wireframe(
     mut wireframe_config: ResMut<Wireframe2dConfig>,
     keyboard: Res<ButtonInput<KeyCode>>,
@@ -486,110 +1765,243 @@ fn toggle_wireframe(
     }
 }
 
-#[derive(Resource)]
-struct Tilemap(pub Vec<Vec<Vec<char>>>);
+/// What the command line asked for:
+/// `[level [number]] [--seed N] [--record FILE | --replay FILE | --verify FILE]`.
+struct Args {
+    /// The level file and level number, if given.
+    level: Vec<String>,
+    seed: Option<u64>,
+    record: Option<String>,
+    replay: Option<String>,
+    verify: Option<String>,
+}
 
-#[derive(Resource)]
-struct NextPacmanPosition {
-    pub pos: Vec3,
-    pub contents: Vec<char>,
+fn parse_args() -> Args {
+    let mut args = Args {
+        level: vec![],
+        seed: None,
+        record: None,
+        replay: None,
+        verify: None,
+    };
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        if !matches!(
+            arg.as_str(),
+            "--seed" | "--record" | "--replay" | "--verify"
+        ) {
+            args.level.push(arg);
+            continue;
+        }
+        let Some(value) = argv.next() else {
+            eprintln!("{arg} needs a value");
+            std::process::exit(1);
+        };
+        match arg.as_str() {
+            "--seed" => match value.parse() {
+                Ok(seed) => args.seed = Some(seed),
+                Err(_) => {
+                    eprintln!("--seed needs a number");
+                    std::process::exit(1);
+                }
+            },
+            "--record" => args.record = Some(value),
+            "--replay" => args.replay = Some(value),
+            _ => args.verify = Some(value),
+        }
+    }
+    args
 }
 
-#[derive(Resource)]
-struct NextGhost1Position {
-    pub pos: Vec3,
-    pub contents: Vec<char>,
+/// Reads the replay at `path`, exiting with the error if it can't be played.
+fn load_replay(path: &str) -> Replay {
+    let source = std::fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("{path}: {err}");
+        std::process::exit(1);
+    });
+    Replay::parse(&source).unwrap_or_else(|err| {
+        eprintln!("{path}:{err}");
+        std::process::exit(1);
+    })
+}
+
+/// The levels named on the command line, or just the bundled one. A level
+/// file is played on its own, while XSB collections (`.sok`/`.xsb`) play
+/// every level from an optional 1-based level number given as a second
+/// argument.
+fn load_levels(args: &[String]) -> Vec<Level> {
+    let mut args = args.iter().cloned();
+    let Some(path) = args.next() else {
+        return vec![Level::classic()];
+    };
+    let source = std::fs::read_to_string(&path).unwrap_or_else(|err| {
+        eprintln!("{path}: {err}");
+        std::process::exit(1);
+    });
+    let fail = |err: LevelError| -> ! {
+        eprintln!("{path}:{err}");
+        std::process::exit(1);
+    };
+    if !(path.ends_with(".sok") || path.ends_with(".xsb")) {
+        return vec![Level::parse(&source).unwrap_or_else(|err| fail(err))];
+    }
+
+    let mut pack = LevelPack::parse(&source).unwrap_or_else(|err| fail(err));
+    let number = args
+        .next()
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(1);
+    if number == 0 || number > pack.levels.len() {
+        eprintln!("{path}: has {} levels, not {number}", pack.levels.len());
+        std::process::exit(1);
+    }
+    pack.levels.split_off(number - 1)
+}
+
+/// Starts the game `replay`, read from `path`, was recorded from on
+/// `levels`, exiting with the error if they don't have the one it starts on.
+fn start_replay(path: &str, replay: &Replay, levels: Vec<Level>) -> Simulation {
+    replay.start(levels).unwrap_or_else(|err| {
+        eprintln!("{path}: {err}");
+        std::process::exit(1);
+    })
+}
+
+/// Plays the replay at `path` back without a window and exits, with status 1
+/// if it doesn't match its recorded checksums.
+fn verify_replay(path: &str) -> ! {
+    let replay = load_replay(path);
+    let mut sim = start_replay(path, &replay, load_levels(&replay.level));
+    match replay.verify(&mut sim) {
+        Ok(()) => {
+            println!("{path}: {} ticks match", replay.checksums.len());
+            std::process::exit(0);
+        }
+        Err(divergence) => {
+            eprintln!("{path}: {divergence}");
+            std::process::exit(1);
+        }
+    }
 }
-use bevy::input::common_conditions::*;
 
 fn main() {
-    App::new()
-        .add_plugins((DefaultPlugins, Wireframe2dPlugin))
-        .insert_resource(start_state())
-        .insert_resource(create_tilemap())
-        .insert_resource(Updates(vec![]))
-        .insert_resource(PacmanMeshes(vec![]))
-        .insert_resource(NextPacmanPosition {
-            pos: Vec3 {
-                x: 2.0,
-                y: 10.0,
-                z: 0.0,
-            },
-            contents: vec![' '],
-        })
+    let mut args = parse_args();
+    if let Some(path) = &args.verify {
+        verify_replay(path);
+    }
+    let playback = args.replay.map(|path| {
+        let replay = load_replay(&path);
+        (path, replay)
+    });
+    if let Some((_, replay)) = &playback {
+        args.level = replay.level.clone();
+        args.seed = Some(replay.seed);
+    }
+    let levels = load_levels(&args.level);
+    let settings = Settings {
+        lives: levels[0].lives,
+    };
+    let seed = args.seed.unwrap_or_else(|| GameRng::from_entropy().seed());
+    println!("Seed: {seed}");
+    let high_scores = load_high_scores();
+    let mut sim = match &playback {
+        Some((path, replay)) => start_replay(path, replay, levels),
+        None => Simulation::from_levels(LevelList::new(levels, 0).expect("a level to play"), seed),
+    };
+    sim.state.scores.high_score = high_scores.scores.best();
+    let mut app = App::new();
+    app.add_plugins((DefaultPlugins, Wireframe2dPlugin, EguiPlugin))
+        .insert_resource(Game(sim))
+        .insert_resource(high_scores)
+        .insert_resource(PendingCommands::default())
+        .insert_resource(Ticks::default())
+        .insert_resource(Time::<Fixed>::from_duration(TICK))
+        .insert_resource(settings)
+        .insert_resource(InputBindings(load_bindings()))
+        .insert_resource(Menu::default())
         .insert_resource(ResourceMap(Vec::new()))
-        .insert_state(AppState::Playing)
-        .add_systems(Startup, (setup_camera, create_resources))
+        // A replay plays straight away, without waiting at the menu.
+        .insert_state(if playback.is_some() {
+            AppState::Playing
+        } else {
+            AppState::MainMenu
+        })
+        .add_event::<RespawnLevel>()
+        .add_event::<InputPressed>()
+        .add_event::<TouchMove>()
+        .init_resource::<TouchControls>()
+        .add_systems(Startup, (setup_camera, respawn_level))
+        .add_systems(OnEnter(AppState::GameOver), record_high_score)
+        .add_systems(OnEnter(AppState::Restarting), restart_game)
+        .add_systems(OnEnter(AppState::Dying), start_dying)
+        .add_systems(OnEnter(AppState::LevelComplete), start_level_complete)
         .add_systems(
             Update,
             (
-                toggle_wireframe,
-                text_input,
-                animate_sprite,
-                (update_next_position, player_move, update_resourcemap)
-                    .chain()
-                    .run_if(on_timer(Duration::from_millis(500))),
+                toggle_wireframe.run_if(in_state(AppState::Playing)),
+                read_inputs,
+                read_swipes,
+                dpad.run_if(in_state(AppState::Playing)),
+                text_input
+                    .after(read_inputs)
+                    .after(read_swipes)
+                    .after(dpad)
+                    .run_if(in_state(AppState::Playing).or_else(in_state(AppState::GameOver))),
+                toggle_pause,
+                hud,
+                main_menu
+                    .after(read_inputs)
+                    .run_if(in_state(AppState::MainMenu)),
+                pause_menu.run_if(in_state(AppState::Paused)),
+                game_over_screen.run_if(in_state(AppState::GameOver)),
+                resume_playback
+                    .run_if(in_state(AppState::GameOver).and_then(resource_exists::<Playback>)),
+                animate_sprite.run_if(in_state(AppState::Playing)),
+                animate_death.run_if(in_state(AppState::Dying)),
+                advance_level.run_if(in_state(AppState::LevelComplete)),
+                change_game_speed,
+                respawn_level
+                    .run_if(on_event::<RespawnLevel>())
+                    .after(advance_level)
+                    .after(animate_death)
+                    .before(tick),
+                (
+                    (tick, checksum_tick).chain().run_if(
+                        in_state(AppState::Playing)
+                            .and_then(state_settled)
+                            .and_then(turn_based_input),
+                    ),
+                    sync_ghosts,
+                    interpolate,
+                )
+                    .chain(),
             ),
         )
-        .run();
-}
-
-fn update_next_position(
-    tilemap: Res<Tilemap>,
-    game_state: Res<GameState>,
-    mut next_pos: ResMut<NextPacmanPosition>,
-) {
-    next_pos.pos = get_next_position(
-        tilemap.as_ref(),
-        game_state.player_position,
-        game_state.current_direction,
-    );
-    next_pos.contents = tilemap.0[next_pos.pos.y as usize][next_pos.pos.x as usize].clone();
-}
-
-fn update_next_position_ghost1(
-    tilemap: Res<Tilemap>,
-    game_state: Res<GameState>,
-    mut next_pos: ResMut<NextGhost1Position>,
-) {
-    next_pos.pos = get_next_position(
-        tilemap.as_ref(),
-        game_state.ghost_1_pos,
-        game_state.ghost_1_cur_dir,
-    );
-    next_pos.contents = tilemap.0[next_pos.pos.y as usize][next_pos.pos.x as usize].clone();
-}
-
-fn get_next_position(
-    tilemap: &Tilemap,
-    position: (i32, i32),
-    direction: Option<MoveDirection>,
-) -> Vec3 {
-    let mut next_pos = Vec3 {
-        x: position.0 as f32,
-        y: position.1 as f32,
-        z: 0.0,
-    };
-    match direction {
-        Some(dir) => match dir {
-            MoveDirection::Up => {
-                next_pos.y = std::cmp::max(0, next_pos.y as i32 - 1) as f32;
-            }
-            MoveDirection::Left => {
-                next_pos.x = std::cmp::max(0, next_pos.x as i32 - 1) as f32;
-            }
-            MoveDirection::Down => {
-                next_pos.y =
-                    std::cmp::min(tilemap.0.len() as i32 - 1, next_pos.y as i32 + 1) as f32;
-            }
-            MoveDirection::Right => {
-                next_pos.x = std::cmp::min(
-                    tilemap.0[next_pos.y as usize].len() as i32 - 1,
-                    next_pos.x as i32 + 1,
-                ) as f32;
+        .add_systems(
+            FixedUpdate,
+            (
+                play_back.run_if(resource_exists::<Playback>),
+                tick,
+                checksum_tick,
+            )
+                .chain()
+                .run_if(in_state(AppState::Playing).and_then(state_settled)),
+        );
+    #[cfg(feature = "serde")]
+    app.add_systems(Update, save_and_resume.before(respawn_level));
+    if let Some((_, replay)) = playback {
+        app.insert_resource(Playback {
+            replay,
+            next: 0,
+            diverged: false,
+        });
+    } else if let Some(path) = args.record {
+        match Recording::create(path.clone(), Replay::new(seed, args.level)) {
+            Ok(recording) => {
+                app.insert_resource(recording);
             }
-        },
-        None => (),
+            Err(err) => eprintln!("{path}: {err}"),
+        }
     }
-    return next_pos;
+    app.run();
 }
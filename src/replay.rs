@@ -0,0 +1,536 @@
+//! Recorded input, for playing a game back exactly.
+//!
+//! A game is fully decided by its seed, its level and the [`Command`]s fed to
+//! [`Simulation::step`] on each tick, so that is all a [`Replay`] needs. It
+//! also keeps a [`Checksum`] of the simulation after every tick, so playback
+//! can tell exactly where it stopped matching. The file is plain text:
+//!
+//! ```text
+//! replay 1
+//! seed 1234
+//! level levels/pack.sok 3
+//! first 1
+//! lives 5
+//! 12 right
+//! value 12 player_position (4, 7)
+//! hash 12 9c1f... 03aa...
+//! 40 up
+//! ```
+//!
+//! Each input line is the number of ticks stepped before the command was read,
+//! then the command. Each `hash` line is the number of ticks stepped and then
+//! one hex hash per [`checksum::FIELDS`](crate::checksum::FIELDS) entry. A
+//! `value` line holds one of those fields written out after a tick, and is only
+//! written when its hash changed, so playback can show what a field should have
+//! been when it differs. The `level` line holds the level arguments the game
+//! was started with and is left out for the bundled level. They load a list of
+//! levels, which the game plays in turn from the one on the `first` line,
+//! counting from 0 and left out for 0. Levels after that are decided by play,
+//! since [`Simulation::step`] moves on to the next level itself. The `lives`
+//! line holds the lives the game started with, and is left out when the first
+//! level's own number of lives was used.
+
+use std::fmt;
+
+use crate::checksum::{self, Checksum, Divergence, FieldDiff, FIELDS};
+use crate::level::{Level, LevelList, NoSuchLevel};
+use crate::simulation::{Command, MoveDirection, Simulation};
+
+/// Bumped whenever the replay format changes.
+pub const REPLAY_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Replay {
+    pub seed: u64,
+    /// The level file and level number the game was started with, empty for
+    /// the bundled level.
+    pub level: Vec<String>,
+    /// Which of the levels `level` loads the game started on.
+    pub first: usize,
+    /// The lives the game started with, if not the first level's own.
+    pub lives: Option<i32>,
+    /// Every command read, with the tick it was read before, in order.
+    pub inputs: Vec<(u32, Command)>,
+    /// The simulation's checksum after each tick, by ticks stepped.
+    pub checksums: Vec<(u32, Checksum)>,
+    /// Each field whenever its hash changed, by ticks stepped and index into
+    /// [`FIELDS`].
+    pub values: Vec<(u32, usize, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayErrorKind {
+    MissingHeader,
+    UnsupportedVersion(u32),
+    MissingSeed,
+    Malformed,
+    BadFirstLevel,
+    BadLives,
+    UnknownCommand(String),
+    /// An input is stamped with an earlier tick than the one before it.
+    OutOfOrder,
+}
+
+/// A problem with a replay file, pointing at the 1-based line it was found
+/// on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayError {
+    pub line: usize,
+    pub kind: ReplayErrorKind,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.line)?;
+        match &self.kind {
+            ReplayErrorKind::MissingHeader => write!(f, "expected `replay {REPLAY_VERSION}`"),
+            ReplayErrorKind::UnsupportedVersion(version) => write!(
+                f,
+                "replay is version {version}, this game reads version {REPLAY_VERSION}"
+            ),
+            ReplayErrorKind::MissingSeed => write!(f, "expected `seed <number>`"),
+            ReplayErrorKind::Malformed => write!(f, "expected `<tick> <command>`"),
+            ReplayErrorKind::BadFirstLevel => write!(f, "expected `first <number>`"),
+            ReplayErrorKind::BadLives => write!(f, "expected `lives <number>`"),
+            ReplayErrorKind::UnknownCommand(name) => write!(f, "unknown command `{name}`"),
+            ReplayErrorKind::OutOfOrder => write!(f, "tick is earlier than the line before"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Replay {
+    pub fn new(seed: u64, level: Vec<String>) -> Self {
+        Replay {
+            seed,
+            level,
+            first: 0,
+            lives: None,
+            inputs: vec![],
+            checksums: vec![],
+            values: vec![],
+        }
+    }
+
+    /// Records `command` as read after `tick` steps.
+    pub fn record(&mut self, tick: u32, command: Command) {
+        self.inputs.push((tick, command));
+    }
+
+    /// Records the checksum of the simulation after `tick` steps.
+    pub fn record_checksum(&mut self, tick: u32, checksum: Checksum) {
+        self.checksums.push((tick, checksum));
+    }
+
+    /// Records `value` as field `field` of [`FIELDS`] after `tick` steps.
+    pub fn record_value(&mut self, tick: u32, field: usize, value: String) {
+        self.values.push((tick, field, value));
+    }
+
+    /// Field `field` of [`FIELDS`] as recorded after `tick` steps, which is
+    /// the last value written for it up to then.
+    pub fn value(&self, tick: u32, field: usize) -> Option<&str> {
+        self.values
+            .iter()
+            .rev()
+            .skip_while(|(at, _, _)| *at > tick)
+            .find(|(_, at_field, _)| *at_field == field)
+            .map(|(_, _, value)| value.as_str())
+    }
+
+    /// Compares `sim` after `tick` steps with the checksum recorded then, if
+    /// there is one.
+    pub fn check(&self, tick: u32, sim: &Simulation) -> Result<(), Divergence> {
+        let Some(expected) = self.checksum(tick) else {
+            return Ok(());
+        };
+        let fields = expected.diff(&Checksum::of(sim));
+        if fields.is_empty() {
+            return Ok(());
+        }
+        let actual = checksum::values(sim);
+        Err(Divergence {
+            tick,
+            fields: fields
+                .into_iter()
+                .map(|field| FieldDiff {
+                    field: FIELDS[field],
+                    expected: self.value(tick, field).map(str::to_string),
+                    actual: actual[field].clone(),
+                })
+                .collect(),
+        })
+    }
+
+    /// The checksum recorded after `tick` steps, if there is one.
+    pub fn checksum(&self, tick: u32) -> Option<&Checksum> {
+        self.checksums
+            .binary_search_by_key(&tick, |(at, _)| *at)
+            .ok()
+            .map(|index| &self.checksums[index].1)
+    }
+
+    /// Starts the game the replay was recorded from, given the levels its
+    /// `level` arguments load, unless they don't have its `first` level.
+    pub fn start(&self, levels: Vec<Level>) -> Result<Simulation, NoSuchLevel> {
+        let levels = LevelList::new(levels, self.first)?;
+        let mut sim = Simulation::from_levels(levels, self.seed);
+        if let Some(lives) = self.lives {
+            sim.set_starting_lives(lives);
+        }
+        Ok(sim)
+    }
+
+    /// Steps `sim` through every recorded input without a frontend, checking
+    /// it against every recorded checksum. `sim` should be the replay's game
+    /// from [`Replay::start`].
+    pub fn verify(&self, sim: &mut Simulation) -> Result<(), Divergence> {
+        let last = self.checksums.last().map_or(0, |(tick, _)| *tick);
+        let mut inputs = self.inputs.iter().peekable();
+        for tick in 0..last {
+            let mut due = vec![];
+            while let Some((_, cmd)) = inputs.next_if(|(at, _)| *at <= tick) {
+                due.push(*cmd);
+            }
+            sim.step(&due);
+            self.check(tick + 1, sim)?;
+        }
+        Ok(())
+    }
+
+    pub fn parse(source: &str) -> Result<Replay, ReplayError> {
+        let error = |line: usize, kind| ReplayError { line, kind };
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l.trim()))
+            .filter(|(_, l)| !l.is_empty());
+
+        let (line_no, header) = lines
+            .next()
+            .ok_or_else(|| error(1, ReplayErrorKind::MissingHeader))?;
+        let version = header
+            .strip_prefix("replay ")
+            .and_then(|v| v.trim().parse::<u32>().ok())
+            .ok_or_else(|| error(line_no, ReplayErrorKind::MissingHeader))?;
+        if version != REPLAY_VERSION {
+            return Err(error(line_no, ReplayErrorKind::UnsupportedVersion(version)));
+        }
+
+        let (line_no, seed) = lines
+            .next()
+            .ok_or_else(|| error(line_no + 1, ReplayErrorKind::MissingSeed))?;
+        let seed = seed
+            .strip_prefix("seed ")
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .ok_or_else(|| error(line_no, ReplayErrorKind::MissingSeed))?;
+
+        let mut replay = Replay::new(seed, vec![]);
+        for (line_no, line) in lines {
+            if let Some(level) = line.strip_prefix("level ") {
+                replay.level = level.split_whitespace().map(str::to_string).collect();
+                continue;
+            }
+            if let Some(first) = line.strip_prefix("first ") {
+                replay.first = first
+                    .trim()
+                    .parse()
+                    .map_err(|_| error(line_no, ReplayErrorKind::BadFirstLevel))?;
+                continue;
+            }
+            if let Some(lives) = line.strip_prefix("lives ") {
+                let lives = lives
+                    .trim()
+                    .parse()
+                    .map_err(|_| error(line_no, ReplayErrorKind::BadLives))?;
+                replay.lives = Some(lives);
+                continue;
+            }
+            if let Some(value) = line.strip_prefix("value ") {
+                let (tick, field, value) =
+                    parse_value(value).ok_or_else(|| error(line_no, ReplayErrorKind::Malformed))?;
+                if replay.values.last().is_some_and(|(last, ..)| *last > tick) {
+                    return Err(error(line_no, ReplayErrorKind::OutOfOrder));
+                }
+                replay.record_value(tick, field, value);
+                continue;
+            }
+            if let Some(hash) = line.strip_prefix("hash ") {
+                let (tick, checksum) = parse_checksum(hash)
+                    .ok_or_else(|| error(line_no, ReplayErrorKind::Malformed))?;
+                if replay
+                    .checksums
+                    .last()
+                    .is_some_and(|(last, _)| *last >= tick)
+                {
+                    return Err(error(line_no, ReplayErrorKind::OutOfOrder));
+                }
+                replay.record_checksum(tick, checksum);
+                continue;
+            }
+            let (tick, name) = line
+                .split_once(' ')
+                .and_then(|(tick, name)| Some((tick.parse::<u32>().ok()?, name.trim())))
+                .ok_or_else(|| error(line_no, ReplayErrorKind::Malformed))?;
+            let command = parse_command(name)
+                .ok_or_else(|| error(line_no, ReplayErrorKind::UnknownCommand(name.to_string())))?;
+            if replay.inputs.last().is_some_and(|(last, _)| *last > tick) {
+                return Err(error(line_no, ReplayErrorKind::OutOfOrder));
+            }
+            replay.record(tick, command);
+        }
+        Ok(replay)
+    }
+
+    /// Writes the replay out, with inputs, values and checksums interleaved
+    /// by tick.
+    pub fn to_text(&self) -> String {
+        let mut out = self.header();
+        let mut checksums = self.checksums.iter().peekable();
+        let mut values = self.values.iter().peekable();
+        let mut write_checksums = |out: &mut String, until: u32| {
+            while let Some((at, checksum)) = checksums.next_if(|(at, _)| *at <= until) {
+                while let Some((tick, field, value)) = values.next_if(|(tick, ..)| tick <= at) {
+                    *out += &value_line(*tick, *field, value);
+                }
+                *out += &checksum_line(*at, checksum);
+            }
+        };
+        for (tick, command) in &self.inputs {
+            write_checksums(&mut out, *tick);
+            out += &input_line(*tick, *command);
+        }
+        write_checksums(&mut out, u32::MAX);
+        for (tick, field, value) in values {
+            out += &value_line(*tick, *field, value);
+        }
+        out
+    }
+
+    /// The lines a replay file starts with, before any input or checksum.
+    pub fn header(&self) -> String {
+        let mut out = format!("replay {REPLAY_VERSION}\nseed {}\n", self.seed);
+        if !self.level.is_empty() {
+            out += &format!("level {}\n", self.level.join(" "));
+        }
+        if self.first != 0 {
+            out += &format!("first {}\n", self.first);
+        }
+        if let Some(lives) = self.lives {
+            out += &format!("lives {lives}\n");
+        }
+        out
+    }
+}
+
+/// The line recording `command` as read after `tick` steps, for writing a
+/// replay out as it is played.
+pub fn input_line(tick: u32, command: Command) -> String {
+    format!("{tick} {}\n", command_name(command))
+}
+
+/// The line recording `checksum` after `tick` steps.
+pub fn checksum_line(tick: u32, checksum: &Checksum) -> String {
+    let hashes: Vec<String> = checksum.0.iter().map(|h| format!("{h:016x}")).collect();
+    format!("hash {tick} {}\n", hashes.join(" "))
+}
+
+/// The line recording `value` as field `field` of [`FIELDS`] after `tick`
+/// steps.
+pub fn value_line(tick: u32, field: usize, value: &str) -> String {
+    format!("value {tick} {} {value}\n", FIELDS[field])
+}
+
+fn parse_value(line: &str) -> Option<(u32, usize, String)> {
+    let (tick, rest) = line.split_once(' ')?;
+    let (name, value) = rest.split_once(' ')?;
+    let field = FIELDS.iter().position(|field| *field == name)?;
+    Some((tick.parse().ok()?, field, value.to_string()))
+}
+
+fn parse_checksum(line: &str) -> Option<(u32, Checksum)> {
+    let mut words = line.split_whitespace();
+    let tick = words.next()?.parse().ok()?;
+    let mut hashes = [0; FIELDS.len()];
+    for hash in &mut hashes {
+        *hash = u64::from_str_radix(words.next()?, 16).ok()?;
+    }
+    words.next().is_none().then_some((tick, Checksum(hashes)))
+}
+
+pub fn command_name(command: Command) -> &'static str {
+    match command {
+        Command::Quit => "quit",
+        Command::Move(MoveDirection::Up) => "up",
+        Command::Move(MoveDirection::Down) => "down",
+        Command::Move(MoveDirection::Left) => "left",
+        Command::Move(MoveDirection::Right) => "right",
+        Command::Reset => "reset",
+        Command::Undo => "undo",
+        Command::Redo => "redo",
+    }
+}
+
+pub(crate) fn parse_command(name: &str) -> Option<Command> {
+    Some(match name {
+        "quit" => Command::Quit,
+        "up" => Command::Move(MoveDirection::Up),
+        "down" => Command::Move(MoveDirection::Down),
+        "left" => Command::Move(MoveDirection::Left),
+        "right" => Command::Move(MoveDirection::Right),
+        "reset" => Command::Reset,
+        "undo" => Command::Undo,
+        "redo" => Command::Redo,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Plays `inputs` on the classic level for `ticks` ticks, recording it
+    /// as a frontend would.
+    fn record(seed: u64, inputs: &[(u32, Command)], ticks: u32) -> Replay {
+        let mut replay = Replay::new(seed, vec![]);
+        let mut sim = replay.start(vec![Level::classic()]).unwrap();
+        let mut last: Option<Checksum> = None;
+        for tick in 0..ticks {
+            let due: Vec<Command> = inputs
+                .iter()
+                .filter(|(at, _)| *at == tick)
+                .map(|(_, cmd)| *cmd)
+                .collect();
+            for cmd in &due {
+                replay.record(tick, *cmd);
+            }
+            sim.step(&due);
+            let checksum = Checksum::of(&sim);
+            for (field, value) in checksum::values(&sim).into_iter().enumerate() {
+                if last.is_some_and(|last| last.0[field] == checksum.0[field]) {
+                    continue;
+                }
+                replay.record_value(tick + 1, field, value);
+            }
+            replay.record_checksum(tick + 1, checksum);
+            last = Some(checksum);
+        }
+        replay
+    }
+
+    fn parse_error(source: &str) -> ReplayError {
+        Replay::parse(source).expect_err("replay should be rejected")
+    }
+
+    const LEFT: Command = Command::Move(MoveDirection::Left);
+    const UP: Command = Command::Move(MoveDirection::Up);
+
+    #[test]
+    fn round_trips_through_text() {
+        let mut replay = record(7, &[(0, LEFT), (10, UP)], 20);
+        replay.level = vec!["pack.sok".to_string(), "3".to_string()];
+        replay.first = 1;
+        replay.lives = Some(5);
+        let text = replay.to_text();
+        assert!(text.starts_with("replay 1\nseed 7\nlevel pack.sok 3\nfirst 1\nlives 5\n"));
+        assert_eq!(Replay::parse(&text).unwrap(), replay);
+    }
+
+    #[test]
+    fn header_defaults() {
+        let replay = Replay::parse("replay 1\nseed 3\n").unwrap();
+        assert_eq!(replay, Replay::new(3, vec![]));
+        assert_eq!(replay.to_text(), "replay 1\nseed 3\n");
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        assert_eq!(parse_error("").kind, ReplayErrorKind::MissingHeader);
+        assert_eq!(
+            parse_error("replay 99\nseed 1\n").kind,
+            ReplayErrorKind::UnsupportedVersion(99)
+        );
+        let err = parse_error("replay 1\n");
+        assert_eq!((err.line, err.kind), (2, ReplayErrorKind::MissingSeed));
+        assert_eq!(
+            parse_error("replay 1\nseed x\n").kind,
+            ReplayErrorKind::MissingSeed
+        );
+        assert_eq!(
+            parse_error("replay 1\nseed 1\nfirst x\n").kind,
+            ReplayErrorKind::BadFirstLevel
+        );
+        assert_eq!(
+            parse_error("replay 1\nseed 1\nlives -\n").kind,
+            ReplayErrorKind::BadLives
+        );
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        let err = parse_error("replay 1\nseed 1\n3 up\nnonsense\n");
+        assert_eq!((err.line, err.kind), (4, ReplayErrorKind::Malformed));
+        assert_eq!(
+            parse_error("replay 1\nseed 1\n3 jump\n").kind,
+            ReplayErrorKind::UnknownCommand("jump".to_string())
+        );
+        assert_eq!(
+            parse_error("replay 1\nseed 1\n5 up\n3 up\n").kind,
+            ReplayErrorKind::OutOfOrder
+        );
+        assert_eq!(
+            parse_error("replay 1\nseed 1\nhash 1 00\n").kind,
+            ReplayErrorKind::Malformed
+        );
+        assert_eq!(
+            parse_error("replay 1\nseed 1\nvalue 1 colour red\n").kind,
+            ReplayErrorKind::Malformed
+        );
+    }
+
+    #[test]
+    fn verifies_a_recording() {
+        let replay = record(11, &[(0, LEFT), (30, UP)], 60);
+        let text = replay.to_text();
+        let replay = Replay::parse(&text).unwrap();
+        let mut sim = replay.start(vec![Level::classic()]).unwrap();
+        assert_eq!(replay.verify(&mut sim), Ok(()));
+    }
+
+    #[test]
+    fn reports_values_where_it_diverges() {
+        let mut replay = record(11, &[(0, LEFT)], 30);
+        replay.inputs.clear();
+        let mut sim = replay.start(vec![Level::classic()]).unwrap();
+        let divergence = replay.verify(&mut sim).unwrap_err();
+        assert_eq!(divergence.tick, 1);
+        assert_eq!(
+            divergence.fields,
+            [FieldDiff {
+                field: "current_direction",
+                expected: Some("Some(Left)".to_string()),
+                actual: "None".to_string(),
+            }]
+        );
+        assert_eq!(
+            divergence.to_string(),
+            "diverged at tick 1:\n  current_direction: expected Some(Left), got None"
+        );
+    }
+
+    #[test]
+    fn starts_on_a_level_it_has() {
+        let mut replay = Replay::parse("replay 1\nseed 1\nfirst 3\n").unwrap();
+        assert_eq!(
+            replay.start(vec![Level::classic()]).err(),
+            Some(NoSuchLevel {
+                first: 3,
+                levels: 1
+            })
+        );
+        replay.first = 0;
+        replay.lives = Some(7);
+        assert_eq!(replay.start(vec![Level::classic()]).unwrap().state.lives, 7);
+    }
+}
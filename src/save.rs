@@ -0,0 +1,111 @@
+//! Saving a game in progress and picking it up again later.
+//!
+//! A save is JSON holding a [`SAVE_VERSION`] next to everything
+//! [`Simulation::step`] needs to carry on: the [`GameState`] with its ghosts,
+//! the map as it is now and as it started, the level's settings, the random
+//! number generator, and the game's [`LevelList`]: every level it plays in
+//! turn, which one it started on and which one it is on. Undo history is not
+//! kept, so a resumed game starts with nothing to undo.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::History;
+use crate::level::LevelList;
+use crate::rng::GameRng;
+use crate::simulation::{GameState, Rules, Simulation};
+use crate::tilemap::{Tilemap, Wrap};
+
+/// Bumped whenever the layout of a save changes, so old saves are turned away
+/// instead of misread.
+pub const SAVE_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct SaveGame {
+    version: u32,
+    state: GameState,
+    tilemap: Tilemap,
+    initial_tilemap: Tilemap,
+    rules: Rules,
+    frightened_seconds: i32,
+    wrap: Wrap,
+    tunnel_slowdown: bool,
+    starting_lives: i32,
+    extra_life_score: i32,
+    rng: GameRng,
+    levels: LevelList,
+}
+
+/// Just enough of a save to read its version before anything else.
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveError {
+    /// The save isn't valid JSON or is missing something.
+    Format(String),
+    /// The save was written by a version of the game that laid it out
+    /// differently.
+    Version(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Format(err) => write!(f, "bad save: {err}"),
+            SaveError::Version(version) => write!(
+                f,
+                "save is version {version}, this game reads version {SAVE_VERSION}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl Simulation {
+    /// Writes the game out so [`Simulation::from_save`] can resume it.
+    pub fn to_save(&self) -> Result<String, SaveError> {
+        let save = SaveGame {
+            version: SAVE_VERSION,
+            state: self.state.clone(),
+            tilemap: self.tilemap.clone(),
+            initial_tilemap: self.initial_tilemap.clone(),
+            rules: self.rules,
+            frightened_seconds: self.frightened_seconds,
+            wrap: self.wrap,
+            tunnel_slowdown: self.tunnel_slowdown,
+            starting_lives: self.starting_lives,
+            extra_life_score: self.extra_life_score,
+            rng: self.rng.clone(),
+            levels: self.levels.clone(),
+        };
+        serde_json::to_string(&save).map_err(|err| SaveError::Format(err.to_string()))
+    }
+
+    pub fn from_save(json: &str) -> Result<Simulation, SaveError> {
+        let format = |err: serde_json::Error| SaveError::Format(err.to_string());
+        let Version { version } = serde_json::from_str(json).map_err(format)?;
+        if version != SAVE_VERSION {
+            return Err(SaveError::Version(version));
+        }
+        let save: SaveGame = serde_json::from_str(json).map_err(format)?;
+        Ok(Simulation {
+            state: save.state,
+            tilemap: save.tilemap,
+            rules: save.rules,
+            frightened_seconds: save.frightened_seconds,
+            wrap: save.wrap,
+            tunnel_slowdown: save.tunnel_slowdown,
+            starting_lives: save.starting_lives,
+            extra_life_score: save.extra_life_score,
+            rng: save.rng,
+            levels: save.levels,
+            history: History::default(),
+            initial_tilemap: save.initial_tilemap,
+        })
+    }
+}
@@ -0,0 +1,731 @@
+//! The game rules, free of any Bevy types.
+//!
+//! A [`Simulation`] owns the [`GameState`] and the [`Tilemap`] and is advanced
+//! one tick at a time with [`Simulation::step`]. Frontends feed it the
+//! [`Command`]s they read and react to the [`GameEvent`]s it returns.
+
+use crate::ghost::{self, GhostMode, GhostState, Ghosts};
+use crate::history::{self, Headings, History};
+use crate::level::{Level, LevelList};
+use crate::rng::GameRng;
+use crate::sokoban;
+use crate::speed::{moves_on, Speeds};
+use crate::tilemap::{Tilemap, Wrap, GHOST, PELLET, PLAYER, POWER_PELLET};
+
+#[derive(Clone, PartialEq, Debug, Hash, Eq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MoveDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl MoveDirection {
+    pub fn opposite(self) -> MoveDirection {
+        match self {
+            MoveDirection::Up => MoveDirection::Down,
+            MoveDirection::Down => MoveDirection::Up,
+            MoveDirection::Left => MoveDirection::Right,
+            MoveDirection::Right => MoveDirection::Left,
+        }
+    }
+
+    pub fn clockwise(self) -> MoveDirection {
+        match self {
+            MoveDirection::Up => MoveDirection::Right,
+            MoveDirection::Right => MoveDirection::Down,
+            MoveDirection::Down => MoveDirection::Left,
+            MoveDirection::Left => MoveDirection::Up,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Hash, Eq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Scores {
+    pub high_score: i32,
+    pub current_score: i32,
+}
+
+#[derive(Clone, PartialEq, Debug, Hash, Eq, Copy)]
+pub enum Command {
+    Quit,
+    Move(MoveDirection),
+    Reset,
+    Undo,
+    Redo,
+}
+
+#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameState {
+    pub current_direction: Option<MoveDirection>,
+    /// A turn asked for that isn't open yet. The player keeps going in
+    /// `current_direction` and takes the turn on the first move it is open
+    /// for, like cornering in the arcade game.
+    pub desired_direction: Option<MoveDirection>,
+    pub player_position: (i32, i32),
+    pub scores: Scores,
+    pub ghosts: Ghosts,
+    /// Lives left, counting the one being played. Losing the last one ends
+    /// the game.
+    pub lives: i32,
+    pub level: i32,
+    pub moves: i32,
+    pub pushes: i32,
+    pub game_over: bool,
+    pub level_complete: bool,
+}
+
+/// Which rule set [`Simulation::step`] plays by.
+#[derive(Clone, PartialEq, Debug, Hash, Eq, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rules {
+    /// The player keeps moving in the last chosen direction every tick,
+    /// eating pellets and avoiding ghosts.
+    #[default]
+    Maze,
+    /// Every move command is one step; boxes are pushed onto goals.
+    Sokoban,
+}
+
+/// Something that happened during a [`Simulation::step`], in the order it
+/// happened. Frontends use these to update whatever they render.
+#[derive(Clone, PartialEq, Debug, Hash, Eq, Copy)]
+pub enum GameEvent {
+    PelletEaten {
+        pos: (i32, i32),
+    },
+    PowerPelletEaten {
+        pos: (i32, i32),
+    },
+    /// An undo put back a pellet or power pellet that had been eaten.
+    PelletRestored {
+        pos: (i32, i32),
+        glyph: char,
+    },
+    PlayerMoved {
+        from: (i32, i32),
+        to: (i32, i32),
+        direction: MoveDirection,
+    },
+    BoxPushed {
+        from: (i32, i32),
+        to: (i32, i32),
+    },
+    GhostMoved {
+        ghost: usize,
+        from: (i32, i32),
+        to: (i32, i32),
+        direction: MoveDirection,
+    },
+    GhostModeChanged(GhostMode),
+    /// A power pellet was eaten and every ghost not already eaten turned
+    /// frightened.
+    GhostsFrightened,
+    FrightEnded,
+    GhostEaten {
+        ghost: usize,
+        points: i32,
+    },
+    /// An eaten ghost made it back to its spawn.
+    GhostRevived {
+        ghost: usize,
+    },
+    /// A ghost caught the player at `pos`. Unless that was the last life,
+    /// the player and ghosts then go back to their spawns.
+    LifeLost {
+        pos: (i32, i32),
+    },
+    /// The player was put back on its spawn after losing a life.
+    PlayerRespawned {
+        from: (i32, i32),
+        to: (i32, i32),
+    },
+    /// The score reached [`Simulation::extra_life_score`].
+    ExtraLife,
+    LevelComplete,
+    /// The next level started, after the last one was complete.
+    LevelStarted,
+    GameOver,
+    Reset,
+}
+
+/// Points for eating a power pellet.
+pub const POWER_PELLET_POINTS: i32 = 50;
+
+/// How long a power pellet frightens the ghosts for when the level doesn't
+/// say, in seconds.
+pub const DEFAULT_FRIGHTENED_SECONDS: i32 = 6;
+
+/// Lives a game starts with when the level doesn't say.
+pub const DEFAULT_LIVES: i32 = 3;
+
+/// The score that earns an extra life when the level doesn't say.
+pub const DEFAULT_EXTRA_LIFE_SCORE: i32 = 1000;
+
+/// The state a level starts in, with the player at `player_position`.
+pub fn start_state(player_position: (i32, i32)) -> GameState {
+    GameState {
+        current_direction: None,
+        desired_direction: None,
+        player_position,
+        scores: Scores {
+            high_score: 0,
+            current_score: 0,
+        },
+        ghosts: Ghosts::new(&[]),
+        lives: 1,
+        level: 1,
+        moves: 0,
+        pushes: 0,
+        game_over: false,
+        level_complete: false,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    pub state: GameState,
+    pub tilemap: Tilemap,
+    pub rules: Rules,
+    /// How long a power pellet frightens the ghosts for, in seconds.
+    pub frightened_seconds: i32,
+    pub wrap: Wrap,
+    /// Whether ghosts move at half speed through tunnels.
+    pub tunnel_slowdown: bool,
+    /// Lives the game starts with, and is given back on a reset.
+    pub starting_lives: i32,
+    /// The score that earns an extra life, once per game. Zero never does.
+    pub extra_life_score: i32,
+    /// Where every random choice of the game comes from. Its seed is what to
+    /// quote to replay a game.
+    pub rng: GameRng,
+    /// The levels this game plays, with the one it is on and the one a reset
+    /// goes back to.
+    pub levels: LevelList,
+    pub(crate) history: History,
+    pub(crate) initial_tilemap: Tilemap,
+}
+
+impl Simulation {
+    /// Starts a game on `tilemap`, placing the player on its `K` tile and a
+    /// ghost on every `m` tile. Ghosts are tracked in [`GameState::ghosts`],
+    /// so their `m` glyphs are taken off the map.
+    fn new(tilemap: Tilemap, levels: LevelList) -> Self {
+        let mut state = start_state(levels.current().player_spawn);
+        let initial_tilemap = tilemap.clone();
+        let mut tilemap = tilemap;
+        let spawns = tilemap.find_all(GHOST);
+        for pos in &spawns {
+            tilemap.remove(*pos, GHOST);
+        }
+        state.ghosts = Ghosts::new(&spawns);
+        Simulation {
+            starting_lives: state.lives,
+            extra_life_score: 0,
+            state,
+            initial_tilemap,
+            tilemap,
+            rules: Rules::default(),
+            frightened_seconds: DEFAULT_FRIGHTENED_SECONDS,
+            wrap: Wrap::default(),
+            tunnel_slowdown: false,
+            rng: GameRng::new(0),
+            levels,
+            history: History::default(),
+        }
+    }
+
+    /// Starts `level` with the rule set, fright time and edges it asks for,
+    /// laying out its pellets from `seed`.
+    pub fn from_level(level: &Level, seed: u64) -> Self {
+        let levels = LevelList::new(vec![level.clone()], 0).expect("one level to start on");
+        Simulation::from_levels(levels, seed)
+    }
+
+    /// Starts a game that plays `levels` in turn, from the one it starts on.
+    pub fn from_levels(levels: LevelList, seed: u64) -> Self {
+        Simulation::start(levels, GameRng::new(seed))
+    }
+
+    /// Starts the current level of `levels` with the settings it asks for.
+    fn start(levels: LevelList, mut rng: GameRng) -> Self {
+        let level = levels.current().clone();
+        let mut sim = Simulation::new(level.tilemap(&mut rng), levels);
+        sim.rng = rng;
+        sim.rules = level.rules;
+        sim.frightened_seconds = level.frightened_seconds;
+        sim.wrap = level.wrap;
+        sim.tunnel_slowdown = level.tunnel_slowdown;
+        sim.starting_lives = level.lives;
+        sim.extra_life_score = level.extra_life_score;
+        sim.state.lives = level.lives;
+        sim
+    }
+
+    /// Moves on to the next of [`Self::levels`] as the next level number,
+    /// carrying over the score and lives. Everyone gets faster with the level
+    /// number (see [`Speeds::for_level`]) and ghosts stay frightened a second
+    /// less per level, down to one second.
+    fn next_level(&mut self) {
+        let number = self.state.level + 1;
+        let mut levels = self.levels.clone();
+        levels.advance();
+        let mut next = Simulation::start(levels, self.rng.clone());
+        next.state.level = number;
+        next.state.scores = self.state.scores;
+        next.state.lives = self.state.lives;
+        next.starting_lives = self.starting_lives;
+        next.frightened_seconds = (next.frightened_seconds - (number - 1)).max(1);
+        *self = next;
+    }
+
+    /// Gives the game `lives` lives instead of what the level asks for, now
+    /// and after every reset.
+    pub fn set_starting_lives(&mut self, lives: i32) {
+        self.starting_lives = lives;
+        self.state.lives = lives;
+    }
+
+    /// Applies `inputs` in order and then advances the game by one tick.
+    ///
+    /// Under [`Rules::Sokoban`] each move is applied as it is read and the
+    /// tick itself does nothing, so stepping with no inputs is a no-op. A step
+    /// that undoes, redoes or resets doesn't advance the game either.
+    ///
+    /// The step after a level is complete starts the next of
+    /// [`Self::levels`] instead, and ignores its inputs.
+    pub fn step(&mut self, inputs: &[Command]) -> Vec<GameEvent> {
+        let mut events = vec![];
+        if self.state.level_complete && !self.state.game_over {
+            self.next_level();
+            events.push(GameEvent::LevelStarted);
+            return events;
+        }
+        let direction_before = self.state.current_direction;
+        let desired_before = self.state.desired_direction;
+        let mut rewound = false;
+        for cmd in inputs {
+            match cmd {
+                Command::Move(dir) => match self.rules {
+                    Rules::Maze => {
+                        self.state.desired_direction = Some(*dir);
+                        self.take_turn();
+                    }
+                    Rules::Sokoban => {
+                        if !self.is_finished() {
+                            let before = self.headings();
+                            let start = events.len();
+                            sokoban::walk(self, *dir, &mut events);
+                            self.history
+                                .record(before, self.headings(), &events[start..]);
+                        }
+                    }
+                },
+                Command::Undo | Command::Redo => {
+                    if !self.state.game_over {
+                        if *cmd == Command::Undo {
+                            history::undo(self, &mut events);
+                        } else {
+                            history::redo(self, &mut events);
+                        }
+                    }
+                    rewound = true;
+                }
+                Command::Quit => {
+                    self.state.game_over = true;
+                    events.push(GameEvent::GameOver);
+                }
+                Command::Reset => {
+                    self.reset();
+                    events.push(GameEvent::Reset);
+                    rewound = true;
+                }
+            }
+        }
+        if self.is_finished() || self.rules == Rules::Sokoban || rewound {
+            self.raise_high_score();
+            return events;
+        }
+
+        let start = events.len();
+        let before = (direction_before, desired_before, self.state.ghosts.clone());
+        let player_from = self.state.player_position;
+        let score_before = self.state.scores.current_score;
+        self.state.ghosts.ticks += 1;
+        if moves_on(self.state.ghosts.ticks, self.player_speed()) {
+            self.player_move(&mut events);
+        }
+        let caught = events[start..]
+            .iter()
+            .any(|event| matches!(event, GameEvent::LifeLost { .. }));
+        if !self.is_finished() && !caught {
+            ghost::advance_phase(self, &mut events);
+            ghost::ghosts_move(self, player_from, &mut events);
+        }
+        self.award_extra_life(score_before, &mut events);
+        self.history
+            .record(before, self.headings(), &events[start..]);
+        self.raise_high_score();
+        events
+    }
+
+    /// Gives an extra life if the score went past [`Self::extra_life_score`]
+    /// since it was `score_before`.
+    fn award_extra_life(&mut self, score_before: i32, events: &mut Vec<GameEvent>) {
+        let threshold = self.extra_life_score;
+        if threshold > 0 && score_before < threshold && self.state.scores.current_score >= threshold
+        {
+            self.state.lives += 1;
+            events.push(GameEvent::ExtraLife);
+        }
+    }
+
+    /// Takes a life for the player being caught at their current position.
+    /// The last life ends the game; otherwise the player goes back to their
+    /// spawn, standing still, and the ghosts go back to theirs.
+    pub(crate) fn lose_life(&mut self, events: &mut Vec<GameEvent>) {
+        let from = self.state.player_position;
+        self.state.lives -= 1;
+        events.push(GameEvent::LifeLost { pos: from });
+        if self.state.lives <= 0 {
+            self.state.game_over = true;
+            events.push(GameEvent::GameOver);
+            return;
+        }
+
+        let to = self.initial_tilemap.find(PLAYER).unwrap_or(from);
+        self.tilemap.remove(from, PLAYER);
+        self.tilemap.cell_mut(to).push(PLAYER);
+        self.state.player_position = to;
+        self.state.current_direction = None;
+        self.state.desired_direction = None;
+        self.state.ghosts.send_home();
+        events.push(GameEvent::PlayerRespawned { from, to });
+    }
+
+    /// Keeps [`Scores::high_score`] at or above the current score.
+    fn raise_high_score(&mut self) {
+        let scores = &mut self.state.scores;
+        scores.high_score = scores.high_score.max(scores.current_score);
+    }
+
+    /// How fast everything moves on the current level.
+    pub fn speeds(&self) -> Speeds {
+        Speeds::for_level(self.state.level)
+    }
+
+    /// How fast the player moves right now, which is a little faster while
+    /// the ghosts are frightened.
+    pub fn player_speed(&self) -> i32 {
+        let speeds = self.speeds();
+        if self.state.ghosts.frightened_ticks > 0 {
+            speeds.player_frightened
+        } else {
+            speeds.player
+        }
+    }
+
+    /// How fast ghost `index` moves right now, going by its state and, on
+    /// levels that slow ghosts in tunnels, where it is.
+    pub fn ghost_speed(&self, index: usize) -> i32 {
+        let speeds = self.speeds();
+        let ghost = &self.state.ghosts.list[index];
+        match ghost.state {
+            GhostState::Eyes => speeds.eyes,
+            GhostState::Frightened => speeds.ghost_frightened,
+            GhostState::Active
+                if self.tunnel_slowdown && self.tilemap.is_tunnel(self.wrap, ghost.pos) =>
+            {
+                speeds.ghost_tunnel
+            }
+            GhostState::Active => speeds.ghost,
+        }
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub(crate) fn headings(&self) -> Headings {
+        (
+            self.state.current_direction,
+            self.state.desired_direction,
+            self.state.ghosts.clone(),
+        )
+    }
+
+    /// Pellets and power pellets still on the map.
+    pub fn pellets_left(&self) -> usize {
+        self.tilemap
+            .0
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|&&c| c == PELLET || c == POWER_PELLET)
+            .count()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.game_over || self.state.level_complete
+    }
+
+    /// Starts the game over from the first of [`Self::levels`], as it was
+    /// started: a fresh [`start_state`] with [`Self::starting_lives`], that
+    /// level's settings and pellets laid out again from the seed. Only the
+    /// high score is kept.
+    pub fn reset(&mut self) {
+        let mut levels = self.levels.clone();
+        levels.restart();
+        let mut fresh = Simulation::start(levels, GameRng::new(self.rng.seed()));
+        fresh.set_starting_lives(self.starting_lives);
+        fresh.state.scores.high_score = self.state.scores.high_score;
+        *self = fresh;
+    }
+
+    /// Turns the player into [`GameState::desired_direction`] if the way is
+    /// open from where they stand.
+    fn take_turn(&mut self) {
+        let Some(wanted) = self.state.desired_direction else {
+            return;
+        };
+        let from = self.state.player_position;
+        let to = get_next_position(&self.tilemap, self.wrap, from, Some(wanted));
+        if to != from && !self.tilemap.is_wall(to) {
+            self.state.current_direction = Some(wanted);
+            self.state.desired_direction = None;
+        }
+    }
+
+    fn player_move(&mut self, events: &mut Vec<GameEvent>) {
+        self.take_turn();
+        let from = self.state.player_position;
+        let Some(direction) = self.state.current_direction else {
+            return;
+        };
+        let to = get_next_position(&self.tilemap, self.wrap, from, Some(direction));
+        if to == from {
+            return;
+        }
+
+        let ghosts = &self.state.ghosts;
+        if ghosts
+            .at(to)
+            .iter()
+            .any(|&index| ghosts.list[index].state == GhostState::Active)
+        {
+            self.lose_life(events);
+            return;
+        }
+
+        if self.tilemap.is_wall(to) {
+            return;
+        }
+
+        let pellet = self.tilemap.cell(to).contains(&PELLET);
+        if pellet {
+            self.state.scores.current_score += 1;
+            self.tilemap.remove(to, PELLET);
+            events.push(GameEvent::PelletEaten { pos: to });
+        }
+        let power = self.tilemap.cell(to).contains(&POWER_PELLET);
+        if power {
+            self.state.scores.current_score += POWER_PELLET_POINTS;
+            self.tilemap.remove(to, POWER_PELLET);
+            events.push(GameEvent::PowerPelletEaten { pos: to });
+        }
+
+        self.tilemap.remove(from, PLAYER);
+        self.tilemap.cell_mut(to).push(PLAYER);
+        self.state.player_position = to;
+        self.state.moves += 1;
+        events.push(GameEvent::PlayerMoved {
+            from,
+            to,
+            direction,
+        });
+
+        if power {
+            ghost::frighten(self, self.frightened_seconds, events);
+        }
+        for index in self.state.ghosts.at(to) {
+            if ghost::touch_player(self, index, events) {
+                break;
+            }
+        }
+
+        if (pellet || power) && !self.state.game_over && self.pellets_left() == 0 {
+            self.state.level_complete = true;
+            events.push(GameEvent::LevelComplete);
+        }
+    }
+}
+
+/// The cell one step from `position` in `direction`. Stepping off an edge
+/// that `wrap` joins up comes back in on the opposite side; any other edge
+/// clamps.
+pub fn get_next_position(
+    tilemap: &Tilemap,
+    wrap: Wrap,
+    position: (i32, i32),
+    direction: Option<MoveDirection>,
+) -> (i32, i32) {
+    let (x, y) = position;
+    let bottom = tilemap.height() as i32 - 1;
+    let right = tilemap.width(y) as i32 - 1;
+    match direction {
+        Some(MoveDirection::Up) if y == 0 && wrap.vertical => (x, bottom),
+        Some(MoveDirection::Down) if y == bottom && wrap.vertical => (x, 0),
+        Some(MoveDirection::Left) if x == 0 && wrap.horizontal => (right, y),
+        Some(MoveDirection::Right) if x == right && wrap.horizontal => (0, y),
+        Some(MoveDirection::Up) => (x, std::cmp::max(0, y - 1)),
+        Some(MoveDirection::Left) => (std::cmp::max(0, x - 1), y),
+        Some(MoveDirection::Down) => (x, std::cmp::min(bottom, y + 1)),
+        Some(MoveDirection::Right) => (std::cmp::min(right, x + 1), y),
+        None => position,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(source: &str) -> Simulation {
+        Simulation::from_level(&Level::parse(source).unwrap(), 1)
+    }
+
+    /// Steps with `inputs` and then without any until `until` happens,
+    /// returning every event on the way.
+    fn run_until(
+        sim: &mut Simulation,
+        inputs: &[Command],
+        until: impl Fn(&GameEvent) -> bool,
+    ) -> Vec<GameEvent> {
+        let mut events = sim.step(inputs);
+        for _ in 0..500 {
+            if events.iter().any(&until) {
+                return events;
+            }
+            events.extend(sim.step(&[]));
+        }
+        panic!("never happened: {events:?}");
+    }
+
+    const RIGHT: Command = Command::Move(MoveDirection::Right);
+
+    #[test]
+    fn eats_pellets_until_the_level_is_complete() {
+        let mut sim = start("pellet_density: 0\n---\n######\n#K••O#\n######\n");
+        assert_eq!(sim.pellets_left(), 3);
+        let events = run_until(&mut sim, &[RIGHT], |e| *e == GameEvent::LevelComplete);
+        let eaten: Vec<&GameEvent> = events
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e,
+                    GameEvent::PelletEaten { .. } | GameEvent::PowerPelletEaten { .. }
+                )
+            })
+            .collect();
+        assert_eq!(
+            eaten,
+            [
+                &GameEvent::PelletEaten { pos: (2, 1) },
+                &GameEvent::PelletEaten { pos: (3, 1) },
+                &GameEvent::PowerPelletEaten { pos: (4, 1) },
+            ]
+        );
+        assert_eq!(sim.state.scores.current_score, 2 + POWER_PELLET_POINTS);
+        assert_eq!(sim.state.scores.high_score, sim.state.scores.current_score);
+        assert!(sim.state.level_complete);
+        assert_eq!(sim.pellets_left(), 0);
+    }
+
+    #[test]
+    fn walls_stop_the_player() {
+        let mut sim = start("pellet_density: 0\n---\n#####\n#K  #\n#####\n");
+        sim.step(&[RIGHT]);
+        for _ in 0..100 {
+            sim.step(&[]);
+        }
+        assert_eq!(sim.state.player_position, (3, 1));
+        assert_eq!(sim.state.moves, 2);
+        assert_eq!(sim.tilemap.find(PLAYER), Some((3, 1)));
+    }
+
+    #[test]
+    fn turns_wait_until_they_are_open() {
+        let mut sim = start("pellet_density: 0\n---\n#####\n#K  #\n### #\n#####\n");
+        sim.step(&[RIGHT]);
+        sim.step(&[Command::Move(MoveDirection::Down)]);
+        assert_eq!(sim.state.desired_direction, Some(MoveDirection::Down));
+        run_until(&mut sim, &[], |e| {
+            matches!(
+                e,
+                GameEvent::PlayerMoved {
+                    direction: MoveDirection::Down,
+                    ..
+                }
+            )
+        });
+        assert_eq!(sim.state.player_position, (3, 2));
+        assert_eq!(sim.state.desired_direction, None);
+    }
+
+    #[test]
+    fn a_ghost_takes_a_life_and_everyone_goes_home() {
+        let mut sim = start("lives: 2\npellet_density: 0\n---\n#######\n#K   m#\n#######\n");
+        let events = run_until(&mut sim, &[RIGHT], |e| {
+            matches!(e, GameEvent::PlayerRespawned { .. })
+        });
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, GameEvent::LifeLost { .. })));
+        assert_eq!(sim.state.lives, 1);
+        assert!(!sim.state.game_over);
+        assert_eq!(sim.state.player_position, (1, 1));
+        assert_eq!(sim.state.current_direction, None);
+        assert_eq!(sim.state.ghosts.list[0].pos, (5, 1));
+    }
+
+    #[test]
+    fn losing_the_last_life_ends_the_game() {
+        let mut sim = start("lives: 1\npellet_density: 0\n---\n#######\n#K   m#\n#######\n");
+        run_until(&mut sim, &[RIGHT], |e| *e == GameEvent::GameOver);
+        assert_eq!(sim.state.lives, 0);
+        assert!(sim.state.game_over);
+        let before = sim.state.clone();
+        assert_eq!(sim.step(&[RIGHT]), []);
+        assert_eq!(sim.state, before);
+    }
+
+    #[test]
+    fn eats_a_frightened_ghost() {
+        let mut sim = start("pellet_density: 0\n---\n########\n#KO   m#\n#•######\n########\n");
+        let events = run_until(&mut sim, &[RIGHT], |e| {
+            matches!(e, GameEvent::GhostEaten { .. })
+        });
+        assert!(events.contains(&GameEvent::GhostsFrightened));
+        assert!(!events
+            .iter()
+            .any(|e| matches!(e, GameEvent::LifeLost { .. })));
+        assert_eq!(sim.state.lives, DEFAULT_LIVES);
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut sim = start("lives: 4\npellet_density: 0\n---\n#####\n#K••#\n#####\n");
+        run_until(&mut sim, &[RIGHT], |e| {
+            matches!(e, GameEvent::PelletEaten { .. })
+        });
+        let events = sim.step(&[Command::Reset]);
+        assert_eq!(events, [GameEvent::Reset]);
+        assert_eq!(sim.state.scores.current_score, 0);
+        assert_eq!(sim.state.scores.high_score, 1);
+        assert_eq!(sim.state.lives, 4);
+        assert_eq!(sim.state.player_position, (1, 1));
+        assert_eq!(sim.pellets_left(), 2);
+    }
+}
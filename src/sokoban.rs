@@ -0,0 +1,128 @@
+//! Sokoban rules: the player walks one cell per move and pushes a single box
+//! ahead of them. The level is solved once every box sits on a goal.
+
+use crate::simulation::{get_next_position, GameEvent, MoveDirection, Simulation};
+use crate::tilemap::{Tilemap, BOX, GOAL, PLAYER};
+
+/// Moves the player one cell in `direction`, pushing a box if there is one
+/// in the way and the cell behind it is free.
+pub(crate) fn walk(sim: &mut Simulation, direction: MoveDirection, events: &mut Vec<GameEvent>) {
+    let from = sim.state.player_position;
+    let to = get_next_position(&sim.tilemap, sim.wrap, from, Some(direction));
+    if to == from || sim.tilemap.is_wall(to) {
+        return;
+    }
+
+    if sim.tilemap.cell(to).contains(&BOX) {
+        let box_to = get_next_position(&sim.tilemap, sim.wrap, to, Some(direction));
+        if box_to == to || sim.tilemap.is_wall(box_to) || sim.tilemap.cell(box_to).contains(&BOX) {
+            return;
+        }
+        sim.tilemap.remove(to, BOX);
+        sim.tilemap.cell_mut(box_to).push(BOX);
+        sim.state.pushes += 1;
+        events.push(GameEvent::BoxPushed {
+            from: to,
+            to: box_to,
+        });
+    }
+
+    sim.tilemap.remove(from, PLAYER);
+    sim.tilemap.cell_mut(to).push(PLAYER);
+    sim.state.player_position = to;
+    sim.state.current_direction = Some(direction);
+    sim.state.moves += 1;
+    events.push(GameEvent::PlayerMoved {
+        from,
+        to,
+        direction,
+    });
+
+    if is_solved(&sim.tilemap) {
+        sim.state.level_complete = true;
+        events.push(GameEvent::LevelComplete);
+    }
+}
+
+/// True when the map has at least one box and none of them are off a goal.
+pub fn is_solved(tilemap: &Tilemap) -> bool {
+    let boxes = tilemap
+        .0
+        .iter()
+        .flatten()
+        .filter(|cell| cell.contains(&BOX));
+    let mut any = false;
+    for cell in boxes {
+        if !cell.contains(&GOAL) {
+            return false;
+        }
+        any = true;
+    }
+    any
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::Level;
+    use crate::simulation::Command;
+
+    fn start(maze: &str) -> Simulation {
+        let level = Level::parse(&format!("rules: sokoban\n---\n{maze}")).unwrap();
+        Simulation::from_level(&level, 1)
+    }
+
+    const RIGHT: Command = Command::Move(MoveDirection::Right);
+
+    #[test]
+    fn pushes_a_box_onto_its_goal() {
+        let mut sim = start("######\n#K$ .#\n######\n");
+        let events = sim.step(&[RIGHT]);
+        assert_eq!(
+            events,
+            [
+                GameEvent::BoxPushed {
+                    from: (2, 1),
+                    to: (3, 1)
+                },
+                GameEvent::PlayerMoved {
+                    from: (1, 1),
+                    to: (2, 1),
+                    direction: MoveDirection::Right
+                },
+            ]
+        );
+        assert!(!is_solved(&sim.tilemap));
+        let events = sim.step(&[RIGHT]);
+        assert_eq!(events.last(), Some(&GameEvent::LevelComplete));
+        assert!(is_solved(&sim.tilemap));
+        assert_eq!((sim.state.moves, sim.state.pushes), (2, 2));
+        assert_eq!(sim.tilemap.cell((4, 1)), &vec![GOAL, BOX]);
+    }
+
+    #[test]
+    fn boxes_stop_at_walls_and_other_boxes() {
+        let mut sim = start("######\n#K$$.#\n#.   #\n######\n");
+        assert_eq!(sim.step(&[RIGHT]), []);
+        let mut sim = start("######\n#K$#.#\n######\n");
+        assert_eq!(sim.step(&[RIGHT]), []);
+        assert_eq!(sim.state.player_position, (1, 1));
+        assert_eq!(sim.state.moves, 0);
+    }
+
+    #[test]
+    fn walls_stop_the_player() {
+        let mut sim = start("#####\n#.$K#\n#####\n");
+        assert_eq!(sim.step(&[RIGHT]), []);
+        assert_eq!(sim.state.player_position, (3, 1));
+    }
+
+    #[test]
+    fn solved_needs_every_box_on_a_goal() {
+        let sim = start("#######\n#K*$ .#\n#######\n");
+        assert!(!is_solved(&sim.tilemap));
+        let sim = start("#####\n#K* #\n#####\n");
+        assert!(is_solved(&sim.tilemap));
+        assert!(!is_solved(&Tilemap(vec![vec![vec![GOAL]]])));
+    }
+}
@@ -0,0 +1,48 @@
+//! How fast the player and the ghosts move.
+//!
+//! Speeds are percentages of full speed, which is one tile per tick.
+//! Anything slower sits out some ticks: at 80% it moves on four ticks out of
+//! five. Like the arcade game, everyone speeds up on later levels, while
+//! frightened ghosts and ghosts in a slow tunnel crawl and eaten ghosts rush
+//! home at full speed.
+
+/// One tile every tick.
+pub const FULL_SPEED: i32 = 100;
+
+/// How fast everything moves on one level, as percentages of [`FULL_SPEED`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Speeds {
+    pub player: i32,
+    /// The player while the ghosts are frightened.
+    pub player_frightened: i32,
+    pub ghost: i32,
+    pub ghost_frightened: i32,
+    /// Ghosts in a tunnel, on levels that slow them there.
+    pub ghost_tunnel: i32,
+    /// Eaten ghosts heading back to their spawn.
+    pub eyes: i32,
+}
+
+impl Speeds {
+    pub fn for_level(level: i32) -> Speeds {
+        let (player, player_frightened, ghost, ghost_frightened, ghost_tunnel) = match level {
+            1 => (80, 90, 75, 50, 40),
+            2..=4 => (90, 95, 85, 55, 45),
+            _ => (100, 100, 95, 60, 50),
+        };
+        Speeds {
+            player,
+            player_frightened,
+            ghost,
+            ghost_frightened,
+            ghost_tunnel,
+            eyes: FULL_SPEED,
+        }
+    }
+}
+
+/// Whether something moving at `speed` gets to move on tick `tick`, counting
+/// from 1.
+pub(crate) fn moves_on(tick: i32, speed: i32) -> bool {
+    tick * speed / FULL_SPEED != (tick - 1) * speed / FULL_SPEED
+}
@@ -0,0 +1,206 @@
+//! Reading moves from swipes across a touch screen.
+//!
+//! A finger has to travel [`Swipes::threshold`] from where it went down, or
+//! from where it last swiped, before its swipe counts. It swipes whichever
+//! way it went furthest, so one stroke can swipe right and then up to turn a
+//! corner. A swipe the same way as the one before from the same finger is
+//! dropped, as is any swipe within [`Swipes::debounce`] seconds of the last,
+//! so a wobbling finger doesn't send a burst of moves.
+
+use crate::simulation::MoveDirection;
+
+/// How far a finger moves before it swipes, in logical pixels.
+pub const DEFAULT_THRESHOLD: f32 = 30.0;
+/// The least time between two swipes, in seconds.
+pub const DEFAULT_DEBOUNCE: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchPhase {
+    Started,
+    Moved,
+    Ended,
+    Canceled,
+}
+
+/// One update to a finger on the screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Touch {
+    /// Tells fingers apart while they are down.
+    pub id: u64,
+    pub phase: TouchPhase,
+    /// Where the finger is, with y growing downwards as on screen.
+    pub position: (f32, f32),
+    /// When it happened, in seconds from any fixed point.
+    pub time: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Swipes {
+    pub threshold: f32,
+    pub debounce: f64,
+    /// Every finger down: where it is measured from and the way it last
+    /// swiped.
+    fingers: Vec<(u64, (f32, f32), Option<MoveDirection>)>,
+    last_swipe: Option<f64>,
+}
+
+impl Default for Swipes {
+    fn default() -> Self {
+        Swipes::new(DEFAULT_THRESHOLD, DEFAULT_DEBOUNCE)
+    }
+}
+
+impl Swipes {
+    pub fn new(threshold: f32, debounce: f64) -> Self {
+        Swipes {
+            threshold,
+            debounce,
+            fingers: vec![],
+            last_swipe: None,
+        }
+    }
+
+    /// Follows `touch`, returning the way it swiped if it did. A finger that
+    /// wasn't seen going down is ignored.
+    pub fn touch(&mut self, touch: &Touch) -> Option<MoveDirection> {
+        if touch.phase == TouchPhase::Started {
+            self.fingers.retain(|(id, ..)| *id != touch.id);
+            self.fingers.push((touch.id, touch.position, None));
+            return None;
+        }
+        let index = self.fingers.iter().position(|(id, ..)| *id == touch.id)?;
+        if touch.phase == TouchPhase::Canceled {
+            self.fingers.remove(index);
+            return None;
+        }
+        let swiped = self.swipe(index, touch);
+        if touch.phase == TouchPhase::Ended {
+            self.fingers.remove(index);
+        }
+        swiped
+    }
+
+    fn swipe(&mut self, index: usize, touch: &Touch) -> Option<MoveDirection> {
+        let (_, from, last) = self.fingers[index];
+        let (dx, dy) = (touch.position.0 - from.0, touch.position.1 - from.1);
+        if dx.hypot(dy) < self.threshold {
+            return None;
+        }
+        let direction = if dx.abs() > dy.abs() {
+            if dx > 0.0 {
+                MoveDirection::Right
+            } else {
+                MoveDirection::Left
+            }
+        } else if dy > 0.0 {
+            MoveDirection::Down
+        } else {
+            MoveDirection::Up
+        };
+        // Measure on from here either way, so only fresh travel counts.
+        self.fingers[index].1 = touch.position;
+        if last == Some(direction)
+            || self
+                .last_swipe
+                .is_some_and(|at| touch.time - at < self.debounce)
+        {
+            return None;
+        }
+        self.fingers[index].2 = Some(direction);
+        self.last_swipe = Some(touch.time);
+        Some(direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn touch(id: u64, phase: TouchPhase, x: f32, y: f32, time: f64) -> Touch {
+        Touch {
+            id,
+            phase,
+            position: (x, y),
+            time,
+        }
+    }
+
+    /// Feeds `touches` through a fresh [`Swipes`], returning every swipe.
+    fn swipes(touches: &[Touch]) -> Vec<MoveDirection> {
+        let mut swipes = Swipes::default();
+        touches.iter().filter_map(|t| swipes.touch(t)).collect()
+    }
+
+    #[test]
+    fn short_movement_does_not_swipe() {
+        use TouchPhase::*;
+        let touches = [
+            touch(1, Started, 0.0, 0.0, 0.0),
+            touch(1, Moved, 20.0, 10.0, 0.1),
+            touch(1, Ended, 25.0, 10.0, 0.2),
+        ];
+        assert_eq!(swipes(&touches), []);
+    }
+
+    #[test]
+    fn swipes_the_way_it_went_furthest() {
+        use TouchPhase::*;
+        let touches = [
+            touch(1, Started, 0.0, 0.0, 0.0),
+            touch(1, Moved, 10.0, -40.0, 0.1),
+            touch(1, Ended, 10.0, -40.0, 0.2),
+        ];
+        assert_eq!(swipes(&touches), [MoveDirection::Up]);
+    }
+
+    #[test]
+    fn swipe_inside_debounce_is_dropped() {
+        use TouchPhase::*;
+        let touches = [
+            touch(1, Started, 0.0, 0.0, 0.0),
+            touch(1, Moved, 40.0, 0.0, 0.10),
+            touch(1, Moved, 40.0, 40.0, 0.15),
+            touch(1, Moved, 0.0, 40.0, 0.30),
+        ];
+        assert_eq!(
+            swipes(&touches),
+            [MoveDirection::Right, MoveDirection::Left]
+        );
+    }
+
+    #[test]
+    fn same_way_again_from_one_finger_is_dropped() {
+        use TouchPhase::*;
+        let touches = [
+            touch(1, Started, 0.0, 0.0, 0.0),
+            touch(1, Moved, 40.0, 0.0, 0.1),
+            touch(1, Moved, 80.0, 0.0, 0.5),
+            touch(1, Moved, 80.0, 40.0, 0.9),
+        ];
+        assert_eq!(
+            swipes(&touches),
+            [MoveDirection::Right, MoveDirection::Down]
+        );
+    }
+
+    #[test]
+    fn canceled_touch_does_not_swipe() {
+        use TouchPhase::*;
+        let touches = [
+            touch(1, Started, 0.0, 0.0, 0.0),
+            touch(1, Canceled, 40.0, 0.0, 0.1),
+            touch(1, Ended, 40.0, 0.0, 0.2),
+        ];
+        assert_eq!(swipes(&touches), []);
+    }
+
+    #[test]
+    fn finger_never_seen_going_down_is_ignored() {
+        use TouchPhase::*;
+        let touches = [
+            touch(2, Moved, 40.0, 0.0, 0.1),
+            touch(2, Ended, 80.0, 0.0, 0.2),
+        ];
+        assert_eq!(swipes(&touches), []);
+    }
+}
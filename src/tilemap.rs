@@ -0,0 +1,110 @@
+//! The maze grid and the glyphs it is drawn with.
+
+pub const WALLS: [char; 4] = ['#', '_', '-', '|'];
+pub const PLAYER: char = 'K';
+pub const GHOST: char = 'm';
+pub const PELLET: char = '•';
+/// Frightens the ghosts when eaten.
+pub const POWER_PELLET: char = 'O';
+/// Floor that never gets a pellet.
+pub const VOID: char = 'Z';
+pub const BOX: char = '$';
+pub const GOAL: char = '.';
+
+/// Which pairs of opposite map edges join up, so that walking off one side
+/// comes back in on the other.
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Wrap {
+    /// The left and right edges.
+    pub horizontal: bool,
+    /// The top and bottom edges.
+    pub vertical: bool,
+}
+
+/// Every cell holds a stack of glyphs, e.g. `['•']` or `['K']`.
+#[derive(Debug, Hash, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tilemap(pub Vec<Vec<Vec<char>>>);
+
+impl Tilemap {
+    pub fn height(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn width(&self, row: i32) -> usize {
+        self.0[row as usize].len()
+    }
+
+    pub fn cell(&self, pos: (i32, i32)) -> &Vec<char> {
+        &self.0[pos.1 as usize][pos.0 as usize]
+    }
+
+    /// The cell at `pos`, or `None` off the edge of the map.
+    pub fn get(&self, pos: (i32, i32)) -> Option<&Vec<char>> {
+        let x = usize::try_from(pos.0).ok()?;
+        let y = usize::try_from(pos.1).ok()?;
+        self.0.get(y)?.get(x)
+    }
+
+    pub fn cell_mut(&mut self, pos: (i32, i32)) -> &mut Vec<char> {
+        &mut self.0[pos.1 as usize][pos.0 as usize]
+    }
+
+    /// Position of the first cell containing `glyph`, scanning row by row.
+    pub fn find(&self, glyph: char) -> Option<(i32, i32)> {
+        self.0.iter().enumerate().find_map(|(y, row)| {
+            row.iter()
+                .position(|cell| cell.contains(&glyph))
+                .map(|x| (x as i32, y as i32))
+        })
+    }
+
+    pub fn find_all(&self, glyph: char) -> Vec<(i32, i32)> {
+        self.0
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.iter()
+                    .enumerate()
+                    .filter(move |(_, cell)| cell.contains(&glyph))
+                    .map(move |(x, _)| (x as i32, y as i32))
+            })
+            .collect()
+    }
+
+    pub fn is_wall(&self, pos: (i32, i32)) -> bool {
+        self.cell(pos).iter().any(|c| WALLS.contains(c))
+    }
+
+    /// True for the cells of a corridor that runs straight off a wrapping
+    /// edge with walls along both sides of it.
+    pub fn is_tunnel(&self, wrap: Wrap, pos: (i32, i32)) -> bool {
+        let walled = |p: (i32, i32)| {
+            self.get(p)
+                .map_or(true, |cell| cell.iter().any(|c| WALLS.contains(c)))
+        };
+        let corridor = |step: (i32, i32)| {
+            let side = (step.1, step.0);
+            let mut p = pos;
+            loop {
+                if walled(p)
+                    || !walled((p.0 + side.0, p.1 + side.1))
+                    || !walled((p.0 - side.0, p.1 - side.1))
+                {
+                    return false;
+                }
+                p = (p.0 + step.0, p.1 + step.1);
+                if self.get(p).is_none() {
+                    return true;
+                }
+            }
+        };
+        (wrap.horizontal && (corridor((-1, 0)) || corridor((1, 0))))
+            || (wrap.vertical && (corridor((0, -1)) || corridor((0, 1))))
+    }
+
+    pub fn remove(&mut self, pos: (i32, i32), glyph: char) {
+        self.cell_mut(pos).retain(|c| *c != glyph);
+    }
+}
@@ -0,0 +1,282 @@
+//! Import and export of the community XSB Sokoban format.
+//!
+//! An XSB board uses `#` walls, `@` the player, `+` the player on a goal,
+//! `$` a box, `*` a box on a goal and `.` a goal. Blank cells are written as
+//! ` `, `-` or `_`. A `.sok` collection holds many boards separated by blank
+//! lines; `Title:` and `Author:` lines after a board describe it, while the
+//! ones before the first board describe the whole pack.
+
+use crate::level::{Level, LevelError, LevelErrorKind};
+use crate::rng::GameRng;
+use crate::simulation::{Rules, DEFAULT_FRIGHTENED_SECONDS, DEFAULT_LIVES};
+use crate::tilemap::{Tilemap, Wrap, BOX, GOAL, PLAYER, WALLS};
+
+const XSB_GLYPHS: [char; 10] = ['#', '@', '+', '$', '*', '.', ' ', '-', '_', '\t'];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LevelPack {
+    pub title: String,
+    pub author: String,
+    pub levels: Vec<Level>,
+}
+
+impl LevelPack {
+    pub fn parse(source: &str) -> Result<LevelPack, LevelError> {
+        let mut pack = LevelPack::default();
+        let mut board: Vec<(usize, &str)> = vec![];
+
+        for (line_no, line) in source.lines().enumerate().map(|(i, l)| (i + 1, l)) {
+            let line = line.trim_end_matches('\r');
+            if is_board_line(line) {
+                board.push((line_no, line));
+                continue;
+            }
+            if !board.is_empty() {
+                pack.levels.push(board_to_level(&board)?);
+                board.clear();
+            }
+
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            let target = match pack.levels.last_mut() {
+                Some(level) => (&mut level.name, &mut level.author),
+                None => (&mut pack.title, &mut pack.author),
+            };
+            match key.trim().to_ascii_lowercase().as_str() {
+                "title" => *target.0 = value,
+                "author" => *target.1 = value,
+                _ => (),
+            }
+        }
+        if !board.is_empty() {
+            pack.levels.push(board_to_level(&board)?);
+        }
+
+        if pack.levels.is_empty() {
+            return Err(LevelError {
+                line: 1,
+                column: 1,
+                kind: LevelErrorKind::EmptyMaze,
+            });
+        }
+        Ok(pack)
+    }
+
+    /// Writes the pack back out as a `.sok` collection.
+    pub fn to_sok(&self) -> String {
+        let mut out = String::new();
+        if !self.title.is_empty() {
+            out += &format!("Title: {}\n", self.title);
+        }
+        if !self.author.is_empty() {
+            out += &format!("Author: {}\n", self.author);
+        }
+        for (i, level) in self.levels.iter().enumerate() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out += &format!("; {}\n", i + 1);
+            // Sokoban levels have no pellets, so nothing is drawn from the rng.
+            out += &to_xsb(&level.tilemap(&mut GameRng::new(0)));
+            if !level.name.is_empty() {
+                out += &format!("Title: {}\n", level.name);
+            }
+            if !level.author.is_empty() {
+                out += &format!("Author: {}\n", level.author);
+            }
+        }
+        out
+    }
+}
+
+/// Draws `tilemap` as an XSB board, one line per row with trailing blanks
+/// trimmed. Anything that isn't part of Sokoban is written as floor.
+pub fn to_xsb(tilemap: &Tilemap) -> String {
+    let mut out = String::new();
+    for row in &tilemap.0 {
+        let line: String = row
+            .iter()
+            .map(|cell| {
+                let goal = cell.contains(&GOAL);
+                if cell.iter().any(|c| WALLS.contains(c)) {
+                    '#'
+                } else if cell.contains(&PLAYER) {
+                    if goal {
+                        '+'
+                    } else {
+                        '@'
+                    }
+                } else if cell.contains(&BOX) {
+                    if goal {
+                        '*'
+                    } else {
+                        '$'
+                    }
+                } else if goal {
+                    '.'
+                } else {
+                    ' '
+                }
+            })
+            .collect();
+        out += line.trim_end();
+        out.push('\n');
+    }
+    out
+}
+
+fn is_board_line(line: &str) -> bool {
+    line.contains('#') && line.chars().all(|c| XSB_GLYPHS.contains(&c))
+}
+
+/// Turns the lines of one board into a Sokoban [`Level`], padding short rows
+/// with floor so the maze is rectangular.
+fn board_to_level(board: &[(usize, &str)]) -> Result<Level, LevelError> {
+    let width = board
+        .iter()
+        .map(|(_, line)| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut grid = vec![];
+    let mut player_spawn = None;
+    let (mut boxes, mut goals) = (0, 0);
+    for (y, (line_no, line)) in board.iter().enumerate() {
+        let mut row: Vec<char> = line
+            .chars()
+            .map(|c| match c {
+                '@' => PLAYER,
+                '-' | '_' | '\t' => ' ',
+                c => c,
+            })
+            .collect();
+        row.resize(width, ' ');
+        for (x, &c) in row.iter().enumerate() {
+            if c == PLAYER || c == '+' {
+                if player_spawn.is_some() {
+                    return Err(LevelError {
+                        line: *line_no,
+                        column: x + 1,
+                        kind: LevelErrorKind::DuplicatePlayerSpawn,
+                    });
+                }
+                player_spawn = Some((x as i32, y as i32));
+            }
+            boxes += usize::from(c == BOX || c == '*');
+            goals += usize::from(c == GOAL || c == '*' || c == '+');
+        }
+        grid.push(row);
+    }
+
+    let first_line = board[0].0;
+    let Some(player_spawn) = player_spawn else {
+        return Err(LevelError {
+            line: first_line,
+            column: 1,
+            kind: LevelErrorKind::MissingPlayerSpawn,
+        });
+    };
+    if boxes != goals {
+        return Err(LevelError {
+            line: first_line,
+            column: 1,
+            kind: LevelErrorKind::BoxGoalMismatch { boxes, goals },
+        });
+    }
+    if boxes == 0 {
+        return Err(LevelError {
+            line: first_line,
+            column: 1,
+            kind: LevelErrorKind::NoBoxes,
+        });
+    }
+
+    Ok(Level {
+        name: String::new(),
+        author: String::new(),
+        pellet_density: 0.0,
+        frightened_seconds: DEFAULT_FRIGHTENED_SECONDS,
+        wrap: Wrap::default(),
+        tunnel_slowdown: false,
+        lives: DEFAULT_LIVES,
+        extra_life_score: 0,
+        rules: Rules::Sokoban,
+        player_spawn,
+        ghost_spawns: vec![],
+        grid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACK: &str = "Title: Two rooms
+Author: Someone
+
+; 1
+#####
+#@$.#
+#####
+Title: Easy
+
+; 2
+######
+#+$ *#
+#    #
+######
+";
+
+    #[test]
+    fn parses_a_pack() {
+        let pack = LevelPack::parse(PACK).unwrap();
+        assert_eq!(pack.title, "Two rooms");
+        assert_eq!(pack.author, "Someone");
+        assert_eq!(pack.levels.len(), 2);
+        let first = &pack.levels[0];
+        assert_eq!(first.name, "Easy");
+        assert_eq!(first.rules, Rules::Sokoban);
+        assert_eq!(first.player_spawn, (1, 1));
+        assert_eq!(pack.levels[1].player_spawn, (1, 1));
+    }
+
+    #[test]
+    fn round_trips_through_sok() {
+        let pack = LevelPack::parse(PACK).unwrap();
+        let again = LevelPack::parse(&pack.to_sok()).unwrap();
+        assert_eq!(again, pack);
+    }
+
+    #[test]
+    fn pads_short_rows() {
+        let pack = LevelPack::parse("####\n#@$.#\n#####\n").unwrap();
+        assert!(pack.levels[0].grid.iter().all(|row| row.len() == 5));
+    }
+
+    #[test]
+    fn rejects_bad_boards() {
+        let kind = |source: &str| LevelPack::parse(source).unwrap_err().kind;
+        assert_eq!(kind("no boards here\n"), LevelErrorKind::EmptyMaze);
+        assert_eq!(
+            kind("#####\n#$. #\n#####\n"),
+            LevelErrorKind::MissingPlayerSpawn
+        );
+        assert_eq!(
+            kind("######\n#@$.@#\n######\n"),
+            LevelErrorKind::DuplicatePlayerSpawn
+        );
+        assert_eq!(
+            kind("######\n#@$$.#\n######\n"),
+            LevelErrorKind::BoxGoalMismatch { boxes: 2, goals: 1 }
+        );
+        assert_eq!(kind("####\n#@ #\n####\n"), LevelErrorKind::NoBoxes);
+    }
+
+    #[test]
+    fn errors_point_at_the_board() {
+        let err =
+            LevelPack::parse("; 1\n#####\n#@$.#\n#####\n\n; 2\n####\n#@ #\n####\n").unwrap_err();
+        assert_eq!((err.line, err.column), (7, 1));
+    }
+}